@@ -31,6 +31,28 @@ pub const RWF_HIPRI: u32 = 0x1;
 //# define RWF_NOWAIT    ((__force __kernel_rwf_t)0x00000008)
 pub const RWF_NOWAIT: u32 = 0x8;
 
+/*
+ * extracted from https://elixir.bootlin.com/linux/latest/source/fs/aio.c#L58
+ * Ring of completion events, which is mapped to user space at the address of the context
+ */
+
+//#define AIO_RING_MAGIC			0xa10a10a1
+pub const AIO_RING_MAGIC: u32 = 0xa10a10a1;
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct aio_ring {
+    pub id: libc::c_uint,
+    pub nr: libc::c_uint,
+    pub head: libc::c_uint,
+    pub tail: libc::c_uint,
+    pub magic: libc::c_uint,
+    pub compat_features: libc::c_uint,
+    pub incompat_features: libc::c_uint,
+    pub header_length: libc::c_uint,
+    pub io_events: [io_event; 0],
+}
+
 // -----------------------------------------------------------------------------------------------
 // Inline functions that wrap the kernel calls for the entry points corresponding to Linux
 // AIO functions
@@ -84,3 +106,15 @@ pub unsafe fn io_getevents(
         timeout,
     )
 }
+
+// Attempt to cancel an outstanding asynchronous I/O operation.
+//
+// See [io_cancel(2)](http://man7.org/linux/man-pages/man2/io_cancel.2.html) for details.
+#[inline(always)]
+pub unsafe fn io_cancel(
+    ctx: aio_context_t,
+    iocb: *mut iocb,
+    result: *mut io_event,
+) -> libc::c_long {
+    syscall(__NR_io_cancel as libc::c_long, ctx, iocb, result)
+}
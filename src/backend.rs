@@ -0,0 +1,289 @@
+use std::os::unix::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fs, io, mem, ptr};
+
+use lock_api::RawMutex;
+
+use crate::thread_pool::ThreadPool;
+use crate::uring::IoUring;
+use crate::{AioBackend, AioContextError, AioContextOptions, AioFallback, AioResult, aio};
+
+/// Kernel interface of the context, selected on creation
+pub(crate) enum Backend<M: RawMutex> {
+    KernelAio(aio::aio_context_t),
+    IoUring(IoUring<M>),
+    ThreadPool(ThreadPool),
+}
+
+impl<M: RawMutex> Backend<M> {
+    /// Set up the backend, requested by the `options`, which signals `eventfd`
+    /// on completions, and return it with the number of slots and the reason of
+    /// the fallback. `IoUring` falls back to `KernelAio` if it's not available,
+    /// and `KernelAio` falls back to `ThreadPool`
+    pub fn new(
+        options: &AioContextOptions,
+        eventfd: BorrowedFd,
+    ) -> Result<(Self, usize, Option<AioFallback>), AioContextError> {
+        let mut fallback = None;
+
+        match options.backend {
+            AioBackend::KernelAio => {}
+            AioBackend::IoUring => match IoUring::new(options.nr as u32, eventfd) {
+                Ok(ring) => return Ok((Backend::IoUring(ring), options.nr, None)),
+                Err(e) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(error = %e, "io_uring is not available, using kernel AIO");
+
+                    fallback = Some(AioFallback::IoUringUnavailable(Arc::new(e)));
+                }
+            },
+            AioBackend::ThreadPool => {
+                let pool =
+                    ThreadPool::new(eventfd, options.nr).map_err(AioContextError::IoSetup)?;
+                return Ok((Backend::ThreadPool(pool), options.nr, None));
+            }
+        }
+
+        let nr = match (read_sysctl("aio-nr"), read_sysctl("aio-max-nr")) {
+            (Some(used), Some(max)) => {
+                match slots_within_limit(options.nr, options.min_nr, used, max) {
+                    Ok(nr) => nr,
+                    Err(e) if options.fail_on_system_limit => return Err(e),
+                    Err(e) => {
+                        #[cfg(feature = "tracing")]
+                        tracing::warn!(error = %e, "AIO system limit exceeded, using blocking thread pool");
+
+                        let pool = ThreadPool::new(eventfd, options.nr)
+                            .map_err(AioContextError::IoSetup)?;
+                        let fallback = match e {
+                            AioContextError::SystemLimit {
+                                requested,
+                                available,
+                            } => AioFallback::SystemLimit {
+                                requested,
+                                available,
+                            },
+                            e => unreachable!("unexpected system limit error {}", e),
+                        };
+
+                        return Ok((Backend::ThreadPool(pool), options.nr, Some(fallback)));
+                    }
+                }
+            }
+            // the limits are not known, so io_setup decides
+            _ => options.nr,
+        };
+
+        let mut context: aio::aio_context_t = 0;
+
+        if unsafe { aio::io_setup(nr as libc::c_long, &mut context) } != 0 {
+            let err = io::Error::last_os_error();
+
+            // aio-max-nr is exhausted, or AIO is not available at all
+            if !matches!(err.raw_os_error(), Some(libc::EAGAIN | libc::ENOSYS)) {
+                return Err(AioContextError::IoSetup(err));
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %err, "io_setup failed, using blocking thread pool");
+
+            let pool = ThreadPool::new(eventfd, nr).map_err(AioContextError::IoSetup)?;
+            return Ok((
+                Backend::ThreadPool(pool),
+                nr,
+                Some(AioFallback::IoSetup(Arc::new(err))),
+            ));
+        }
+
+        Ok((Backend::KernelAio(context), nr, fallback))
+    }
+
+    /// Which backend is in use
+    pub fn kind(&self) -> AioBackend {
+        match self {
+            Backend::KernelAio(_) => AioBackend::KernelAio,
+            Backend::IoUring(_) => AioBackend::IoUring,
+            Backend::ThreadPool(_) => AioBackend::ThreadPool,
+        }
+    }
+
+    /// Submit the control blocks. Return the number of the accepted ones,
+    /// or the error if none was accepted
+    ///
+    /// # Safety
+    ///
+    /// The control blocks and the buffers they refer to must stay valid until completed
+    pub unsafe fn submit(&self, iocbs: &mut [*mut aio::iocb]) -> io::Result<usize> {
+        match self {
+            Backend::KernelAio(context) => {
+                let result =
+                    aio::io_submit(*context, iocbs.len() as libc::c_long, iocbs.as_mut_ptr());
+
+                if result < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(result as usize)
+                }
+            }
+            Backend::IoUring(ring) => ring.submit(iocbs),
+            Backend::ThreadPool(pool) => Ok(pool.submit(iocbs)),
+        }
+    }
+
+    /// Ask the kernel to cancel the submitted control block
+    ///
+    /// Returns `Ok(None)` if cancellation is in progress, and the completion event will be
+    /// delivered as usual. Legacy kernel AIO returns the event directly, in which
+    /// case `Ok(Some(res))` is returned, and no completion will be delivered.
+    pub fn cancel(&self, iocb: *mut aio::iocb) -> io::Result<Option<AioResult>> {
+        match self {
+            Backend::KernelAio(context) => {
+                let mut event: aio::io_event = unsafe { mem::zeroed() };
+
+                if unsafe { aio::io_cancel(*context, iocb, &mut event) } == 0 {
+                    return Ok(Some(event.res));
+                }
+
+                let err = io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::EINPROGRESS) {
+                    Ok(None)
+                } else {
+                    Err(err)
+                }
+            }
+            // the outcome of the cancellation is known only from the completion
+            Backend::IoUring(ring) => ring.cancel(unsafe { &*iocb }).map(|_| None),
+            Backend::ThreadPool(pool) => pool.cancel(unsafe { &*iocb }).map(|_| None),
+        }
+    }
+
+    /// Like `cancel`, but doesn't wait for the outcome of the io_uring cancellation,
+    /// so it never blocks. Refused cancellations are not reported then
+    pub fn cancel_nowait(&self, iocb: *mut aio::iocb) -> io::Result<Option<AioResult>> {
+        match self {
+            Backend::IoUring(ring) => ring.cancel_nowait(unsafe { &*iocb }).map(|_| None),
+            _ => self.cancel(iocb),
+        }
+    }
+
+    /// Fetch from `min_nr` to `events.capacity()` completions, waiting up to `timeout`.
+    /// Return the number of completions, which is zero if the wait is interrupted
+    pub fn get_events(
+        &self,
+        min_nr: usize,
+        timeout: Option<Duration>,
+        events: &mut Vec<aio::io_event>,
+    ) -> io::Result<usize> {
+        let context = match self {
+            Backend::KernelAio(context) => *context,
+            Backend::IoUring(ring) => return ring.get_events(min_nr, timeout, events),
+            Backend::ThreadPool(pool) => return Ok(pool.get_events(min_nr, timeout, events)),
+        };
+
+        let mut timeout = timeout.map(|timeout| aio::timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        });
+
+        let num_received = unsafe {
+            aio::io_getevents(
+                context,
+                min_nr as libc::c_long,
+                events.capacity() as libc::c_long,
+                events.as_mut_ptr(),
+                timeout
+                    .as_mut()
+                    .map_or(ptr::null_mut(), |timeout| timeout as *mut _),
+            )
+        };
+
+        if num_received < 0 {
+            let err = io::Error::last_os_error();
+            // interrupted wait is not an error, the caller retries
+            if err.kind() == io::ErrorKind::Interrupted {
+                return Ok(0);
+            }
+
+            return Err(err);
+        }
+
+        unsafe { events.set_len(num_received as usize) };
+
+        Ok(num_received as usize)
+    }
+}
+
+impl<M: RawMutex> Drop for Backend<M> {
+    fn drop(&mut self) {
+        if let Backend::KernelAio(context) = self {
+            let result = unsafe { aio::io_destroy(*context) };
+            assert_eq!(0, result, "io_destroy returned bad code");
+        }
+    }
+}
+
+/// Read the counter from `/proc/sys/fs`
+fn read_sysctl(name: &str) -> Option<usize> {
+    fs::read_to_string(format!("/proc/sys/fs/{}", name))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Number of slots, which `used` of `max` system-wide AIO slots allow. `nr` is shrunk
+/// down to `min_nr`, if it's set
+fn slots_within_limit(
+    nr: usize,
+    min_nr: Option<usize>,
+    used: usize,
+    max: usize,
+) -> Result<usize, AioContextError> {
+    let available = max.saturating_sub(used);
+
+    match min_nr {
+        _ if nr <= available => Ok(nr),
+        Some(min_nr) if min_nr.max(1) <= available => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                requested = nr,
+                available,
+                "shrinking AIO context to the system limit"
+            );
+
+            Ok(available)
+        }
+        _ => Err(AioContextError::SystemLimit {
+            requested: nr,
+            available,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_shrink_to_limit() {
+        assert_eq!(8, slots_within_limit(8, None, 0, 65536).unwrap());
+        assert_eq!(8, slots_within_limit(8, Some(2), 65528, 65536).unwrap());
+        assert_eq!(4, slots_within_limit(8, Some(2), 65532, 65536).unwrap());
+
+        assert!(matches!(
+            slots_within_limit(8, None, 65532, 65536),
+            Err(AioContextError::SystemLimit {
+                requested: 8,
+                available: 4
+            })
+        ));
+        assert!(matches!(
+            slots_within_limit(8, Some(6), 65540, 65536),
+            Err(AioContextError::SystemLimit {
+                requested: 8,
+                available: 0
+            })
+        ));
+    }
+}
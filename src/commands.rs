@@ -1,6 +1,6 @@
-use crate::flags::{ReadFlags, WriteFlags};
+use crate::flags::{PollFlags, ReadFlags, WriteFlags};
 use crate::locked_buf::LifetimeExtender;
-use crate::{LockedBuf, aio};
+use crate::{AioBuffer, AioCommandError, aio};
 
 /// Raw AIO command
 #[derive(Debug)]
@@ -10,7 +10,7 @@ pub enum RawCommand<'a> {
         /// Offset
         offset: u64,
         /// Buffer
-        buffer: &'a mut LockedBuf,
+        buffer: &'a mut dyn AioBuffer,
         /// Read flags
         flags: ReadFlags,
         /// Optional len
@@ -22,7 +22,7 @@ pub enum RawCommand<'a> {
         /// Offset
         offset: u64,
         /// Buffer
-        buffer: &'a LockedBuf,
+        buffer: &'a dyn AioBuffer,
 
         /// Write flags
         flags: WriteFlags,
@@ -30,6 +30,32 @@ pub enum RawCommand<'a> {
         len: u64,
     },
 
+    /// Vectored read. Fills the buffers in order, each one to its full size
+    Preadv {
+        /// Offset
+        offset: u64,
+        /// Buffers
+        buffers: &'a mut [&'a mut dyn AioBuffer],
+        /// Read flags
+        flags: ReadFlags,
+    },
+
+    /// Vectored write. Writes the buffers in order, each one in its full size
+    Pwritev {
+        /// Offset
+        offset: u64,
+        /// Buffers
+        buffers: &'a [&'a dyn AioBuffer],
+        /// Write flags
+        flags: WriteFlags,
+    },
+
+    /// Wait for the readiness of the file descriptor. Requires Linux 4.18+
+    Poll {
+        /// Requested events
+        events: PollFlags,
+    },
+
     /// Sync data only
     Fdsync,
 
@@ -44,6 +70,9 @@ impl<'a> RawCommand<'a> {
         match self {
             Pread { .. } => aio::IOCB_CMD_PREAD,
             Pwrite { .. } => aio::IOCB_CMD_PWRITE,
+            Preadv { .. } => aio::IOCB_CMD_PREADV,
+            Pwritev { .. } => aio::IOCB_CMD_PWRITEV,
+            Poll { .. } => aio::IOCB_CMD_POLL,
             Fdsync => aio::IOCB_CMD_FDSYNC,
             Fsync => aio::IOCB_CMD_FSYNC,
         }
@@ -55,6 +84,9 @@ impl<'a> RawCommand<'a> {
         match *self {
             Pread { offset, .. } => Some(offset),
             Pwrite { offset, .. } => Some(offset),
+            Preadv { offset, .. } => Some(offset),
+            Pwritev { offset, .. } => Some(offset),
+            Poll { .. } => None,
             Fdsync => None,
             Fsync => None,
         }
@@ -66,6 +98,9 @@ impl<'a> RawCommand<'a> {
         match *self {
             Pread { len, .. } => Some(len),
             Pwrite { len, .. } => Some(len),
+            Preadv { .. } => None,
+            Pwritev { .. } => None,
+            Poll { .. } => None,
             Fdsync => None,
             Fsync => None,
         }
@@ -77,30 +112,77 @@ impl<'a> RawCommand<'a> {
         match self {
             Pread { buffer, .. } => Some(buffer.aio_addr_and_len()),
             Pwrite { buffer, .. } => Some(buffer.aio_addr_and_len()),
+            Preadv { .. } => None,
+            Pwritev { .. } => None,
+            Poll { .. } => None,
             Fdsync => None,
             Fsync => None,
         }
     }
 
+    /// Reject the command, whose length exceeds the buffer size
+    pub(crate) fn check_len(&self) -> Result<(), AioCommandError> {
+        match (self.len(), self.buffer_addr()) {
+            (Some(len), Some((_, buffer_size))) if len > buffer_size => {
+                Err(AioCommandError::InvalidLength { len, buffer_size })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn poll_events(&self) -> Option<u64> {
+        match self {
+            RawCommand::Poll { events } => Some(events.bits() as u16 as u64),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn vectored_buffers(&self) -> Option<impl Iterator<Item = &dyn AioBuffer>> {
+        use RawCommand::*;
+
+        let (read_buffers, write_buffers): (&[&mut dyn AioBuffer], &[&dyn AioBuffer]) = match self {
+            Preadv { buffers, .. } => (buffers, &[]),
+            Pwritev { buffers, .. } => (&[], buffers),
+            _ => return None,
+        };
+
+        Some(
+            read_buffers
+                .iter()
+                .map(|buffer| &**buffer)
+                .chain(write_buffers.iter().copied()),
+        )
+    }
+
     pub(crate) fn flags(&self) -> Option<u32> {
         use RawCommand::*;
 
         match self {
             Pread { flags, .. } => Some(flags.bits() as _),
             Pwrite { flags, .. } => Some(flags.bits() as _),
+            Preadv { flags, .. } => Some(flags.bits() as _),
+            Pwritev { flags, .. } => Some(flags.bits() as _),
+            Poll { .. } => None,
             Fdsync => None,
             Fsync => None,
         }
     }
 
-    pub(crate) fn buffer_lifetime_extender(&self) -> Option<LifetimeExtender> {
+    pub(crate) fn buffer_lifetime_extenders(&self, extenders: &mut Vec<LifetimeExtender>) {
         use RawCommand::*;
 
         match self {
-            Pread { buffer, .. } => Some(buffer.lifetime_extender()),
-            Pwrite { buffer, .. } => Some(buffer.lifetime_extender()),
-            Fdsync => None,
-            Fsync => None,
+            Pread { buffer, .. } => extenders.push(buffer.lifetime_extender()),
+            Pwrite { buffer, .. } => extenders.push(buffer.lifetime_extender()),
+            Preadv { buffers, .. } => {
+                extenders.extend(buffers.iter().map(|buffer| buffer.lifetime_extender()))
+            }
+            Pwritev { buffers, .. } => {
+                extenders.extend(buffers.iter().map(|buffer| buffer.lifetime_extender()))
+            }
+            Poll { .. } => {}
+            Fdsync => {}
+            Fsync => {}
         }
     }
 }
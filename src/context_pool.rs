@@ -0,0 +1,171 @@
+use std::os::unix::prelude::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{fmt, io, thread};
+
+use crate::{
+    AioCommandError, AioContext, AioContextError, AioContextHandle, RawCommand, generic_aio_context,
+};
+
+/// How [`AioContextPoolHandle`] picks the context for the request
+///
+/// [`AioContextPoolHandle`]: struct.AioContextPoolHandle.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardRouting {
+    /// Every thread sticks to one context, assigned round-robin on the first use
+    ThreadAffinity,
+    /// Requests to the same file descriptor go to the same context
+    FdHash,
+}
+
+static NEXT_THREAD_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static THREAD_SHARD: usize = NEXT_THREAD_SHARD.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Set of independent AIO contexts, to spread the load of submissions and completions
+///
+/// Every context has its own requests pool, eventfd and background task, so they
+/// don't contend with each other. Must be kept while AIO is in use, like [`AioContext`].
+///
+/// [`AioContext`]: type.AioContext.html
+pub struct AioContextPool {
+    contexts: Vec<AioContext>,
+    handle: AioContextPoolHandle,
+}
+
+impl fmt::Debug for AioContextPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AioContextPool")
+            .field("contexts", &self.contexts)
+            .field("routing", &self.handle.routing)
+            .finish()
+    }
+}
+
+impl AioContextPool {
+    /// Create `num_contexts` contexts with `nr` slots each, and spawn their background
+    /// tasks with `tokio::spawn`. See [`generic_aio_context`] for the meaning of `use_semaphore`.
+    /// Zero `num_contexts` fails with `IoSetup` error `EINVAL`
+    ///
+    /// [`generic_aio_context`]: fn.generic_aio_context.html
+    pub fn new(
+        num_contexts: usize,
+        nr: usize,
+        use_semaphore: bool,
+        routing: ShardRouting,
+    ) -> Result<AioContextPool, AioContextError> {
+        // rejected like the context with zero slots
+        if num_contexts == 0 {
+            return Err(AioContextError::IoSetup(io::Error::from_raw_os_error(
+                libc::EINVAL,
+            )));
+        }
+
+        let mut contexts = Vec::with_capacity(num_contexts);
+        let mut handles = Vec::with_capacity(num_contexts);
+
+        for _ in 0..num_contexts {
+            let (context, handle, background) = generic_aio_context(nr, use_semaphore)?;
+            tokio::spawn(background);
+
+            contexts.push(context);
+            handles.push(handle);
+        }
+
+        Ok(AioContextPool {
+            contexts,
+            handle: AioContextPoolHandle {
+                handles: handles.into(),
+                routing,
+            },
+        })
+    }
+
+    /// Create the context per available CPU core. See [`new`]
+    ///
+    /// [`new`]: struct.AioContextPool.html#method.new
+    pub fn per_core(
+        nr: usize,
+        use_semaphore: bool,
+        routing: ShardRouting,
+    ) -> Result<AioContextPool, AioContextError> {
+        let num_cores = thread::available_parallelism().map_or(1, |n| n.get());
+
+        Self::new(num_cores, nr, use_semaphore, routing)
+    }
+
+    /// Cloneable handle, which routes requests to the contexts of the pool
+    pub fn handle(&self) -> AioContextPoolHandle {
+        self.handle.clone()
+    }
+
+    /// Number of contexts in the pool
+    pub fn num_contexts(&self) -> usize {
+        self.contexts.len()
+    }
+
+    /// Number of available AIO slots left in all contexts of the pool
+    ///
+    /// Return None if `use_semaphore` was set to `false`
+    pub fn available_slots(&self) -> Option<usize> {
+        self.contexts.iter().map(|c| c.available_slots()).sum()
+    }
+
+    /// Close all contexts and wait for all related running futures to complete.
+    pub async fn close(self) {
+        for context in self.contexts {
+            context.close().await;
+        }
+    }
+}
+
+/// Cloneable handle to [`AioContextPool`]
+///
+/// [`AioContextPool`]: struct.AioContextPool.html
+#[derive(Clone)]
+pub struct AioContextPoolHandle {
+    handles: Arc<[AioContextHandle]>,
+    routing: ShardRouting,
+}
+
+impl fmt::Debug for AioContextPoolHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AioContextPoolHandle")
+            .field("num_contexts", &self.handles.len())
+            .field("routing", &self.routing)
+            .finish()
+    }
+}
+
+impl AioContextPoolHandle {
+    /// Handle of the context, which should serve the requests to `fd`
+    pub fn handle_for(&self, fd: &impl AsRawFd) -> &AioContextHandle {
+        let index = match self.routing {
+            ShardRouting::ThreadAffinity => THREAD_SHARD.with(|shard| *shard),
+            ShardRouting::FdHash => fd.as_raw_fd() as usize,
+        };
+
+        &self.handles[index % self.handles.len()]
+    }
+
+    /// Number of available AIO slots left in all contexts of the pool
+    ///
+    /// Return None if AIO contexts stopped, or if `use_semaphore`
+    /// was set to `false`
+    pub fn available_slots(&self) -> Option<usize> {
+        self.handles.iter().map(|h| h.available_slots()).sum()
+    }
+
+    /// Submit command to the context, picked for `fd`. See [`submit_request`]
+    ///
+    /// [`submit_request`]: struct.GenericAioContextHandle.html#method.submit_request
+    pub async fn submit_request(
+        &self,
+        fd: &impl AsRawFd,
+        command: RawCommand<'_>,
+    ) -> Result<u64, AioCommandError> {
+        self.handle_for(fd).submit_request(fd, command).await
+    }
+}
@@ -1,4 +1,5 @@
 use std::io;
+use std::sync::Arc;
 
 use thiserror::Error;
 
@@ -11,12 +12,22 @@ pub enum AioCommandError {
     #[error("AioContext stopped")]
     AioStopped,
 
+    /// Background task, which polls the completions, failed
+    #[error("AIO poller failed: {0}")]
+    PollerFailed(#[source] Arc<io::Error>),
+
     /// Error from [`io_submit`]
     ///
     /// [`io_submit`]: https://manpages.debian.org/testing/manpages-dev/io_submit.2.en.html
     #[error("io_submit error: {0}")]
     IoSubmit(#[source] io::Error),
 
+    /// Error from [`io_cancel`]
+    ///
+    /// [`io_cancel`]: https://manpages.debian.org/testing/manpages-dev/io_cancel.2.en.html
+    #[error("io_cancel error: {0}")]
+    IoCancel(#[source] io::Error),
+
     /// Bad result received
     #[error("bad result: `{0}`")]
     BadResult(#[source] io::Error),
@@ -25,12 +36,75 @@ pub enum AioCommandError {
     #[error("non-zero code returned")]
     NonZeroCode,
 
+    /// The offset, the length or the buffer address doesn't satisfy the `O_DIRECT`
+    /// alignment restrictions of the file
+    #[error("{what} {value} is not aligned to {alignment} bytes, as required by O_DIRECT")]
+    Misaligned {
+        /// Misaligned value: `offset`, `length` or `buffer address`
+        what: &'static str,
+        /// The value itself
+        value: u64,
+        /// Required alignment
+        alignment: u64,
+    },
+
+    /// The length of the command exceeds the size of its buffer
+    #[error("length {len} exceeds the buffer size {buffer_size}")]
+    InvalidLength {
+        /// Requested length
+        len: u64,
+        /// Size of the buffer
+        buffer_size: u64,
+    },
+
+    /// The file ended before the whole length was read
+    #[error("unexpected end of file: {transferred} of {len} bytes read")]
+    UnexpectedEof {
+        /// Requested length
+        len: u64,
+        /// Bytes read before the end of file
+        transferred: u64,
+    },
+
+    /// The kernel stopped writing before the whole length was written
+    #[error("failed to write the whole buffer: {transferred} of {len} bytes written")]
+    WriteZero {
+        /// Requested length
+        len: u64,
+        /// Bytes written
+        transferred: u64,
+    },
+
+    /// The deadline passed before the request completed
+    ///
+    /// `cancelled` is `true` if the request had no effect: it was either never submitted,
+    /// or cancelled by the kernel. Otherwise, the kernel refused to cancel the request,
+    /// and it may still complete in the background.
+    #[error("request timed out (cancelled: {cancelled})")]
+    TimedOut {
+        /// Whether the request is known to have no effect
+        cancelled: bool,
+    },
+
     /// The capacity of AIO context exceeded. Happens if `use_semaphore` set to `false`
     /// and the code attempts to send more requests than kernel-threads.
     #[error("capacity exceeded")]
     CapacityExceeded,
 }
 
+impl From<AioCommandError> for io::Error {
+    fn from(e: AioCommandError) -> io::Error {
+        match e {
+            AioCommandError::IoSubmit(e) | AioCommandError::BadResult(e) => e,
+            e @ AioCommandError::UnexpectedEof { .. } => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, e)
+            }
+            e @ AioCommandError::WriteZero { .. } => io::Error::new(io::ErrorKind::WriteZero, e),
+            e => io::Error::other(e),
+        }
+    }
+}
+
 /// AIO context creation error
 #[derive(Error, Debug)]
 pub enum AioContextError {
@@ -43,4 +117,13 @@ pub enum AioContextError {
     /// Error from `io_setup`
     #[error("io_setup error: `{0}`")]
     IoSetup(#[from] io::Error),
+
+    /// The system-wide limit of AIO slots `fs.aio-max-nr` doesn't allow the context
+    #[error("requested {requested} AIO slots, but only {available} are available (fs.aio-max-nr)")]
+    SystemLimit {
+        /// Requested number of slots
+        requested: usize,
+        /// Number of slots left under the limit
+        available: usize,
+    },
 }
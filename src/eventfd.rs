@@ -1,16 +1,24 @@
 #![allow(clippy::enum_variant_names)]
 
-use std::fs::File;
-use std::io::{self, Read, Write};
-use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
-use std::pin::Pin;
-use std::task::{Context, Poll};
-use std::{fmt, mem, slice};
+use std::io;
+use std::os::unix::io::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+#[cfg(feature = "tokio")]
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Write},
+    mem,
+    os::unix::io::RawFd,
+    pin::Pin,
+    slice,
+    task::{Context, Poll},
+};
 
 use libc::eventfd;
 use thiserror::Error;
-use tokio::io::Ready;
-use tokio::io::unix::AsyncFd;
+#[cfg(feature = "tokio")]
+use tokio::io::{Ready, unix::AsyncFd};
+#[cfg(feature = "tokio")]
 use tokio_stream::Stream;
 
 #[derive(Error, Debug)]
@@ -23,10 +31,57 @@ pub enum EventFdError {
     ReadError(#[source] io::Error),
 }
 
+/// Create non-blocking eventfd with `init` permits
+pub(crate) fn new_fd(init: usize, semaphore: bool) -> Result<OwnedFd, EventFdError> {
+    let flags = if semaphore {
+        libc::O_CLOEXEC | libc::EFD_NONBLOCK | libc::EFD_SEMAPHORE
+    } else {
+        libc::O_CLOEXEC | libc::EFD_NONBLOCK
+    };
+
+    let fd = unsafe { eventfd(init as libc::c_uint, flags) };
+
+    if fd < 0 {
+        return Err(EventFdError::CreateError(io::Error::last_os_error()));
+    }
+
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Read and reset the counter of non-blocking eventfd. Return 0 if it's not signalled
+pub(crate) fn try_read(fd: BorrowedFd<'_>) -> Result<u64, EventFdError> {
+    let mut result = 0u64;
+
+    let rc = unsafe {
+        libc::read(
+            fd.as_raw_fd(),
+            &mut result as *mut u64 as *mut libc::c_void,
+            8,
+        )
+    };
+
+    if rc < 0 {
+        let err = io::Error::last_os_error();
+        return match err.kind() {
+            io::ErrorKind::WouldBlock => Ok(0),
+            _ => Err(EventFdError::ReadError(err)),
+        };
+    }
+
+    assert_eq!(
+        8, rc,
+        "Reading from an eventfd should transfer exactly 8 bytes"
+    );
+
+    Ok(result)
+}
+
+#[cfg(feature = "tokio")]
 pub struct EventFdInner {
     pub inner: File,
 }
 
+#[cfg(feature = "tokio")]
 impl AsRawFd for EventFdInner {
     fn as_raw_fd(&self) -> RawFd {
         self.inner.as_raw_fd()
@@ -34,16 +89,19 @@ impl AsRawFd for EventFdInner {
 }
 
 /// Tokio-aware EventFd implementation
+#[cfg(feature = "tokio")]
 pub struct EventFd {
     evented: AsyncFd<EventFdInner>,
 }
 
+#[cfg(feature = "tokio")]
 impl fmt::Debug for EventFd {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("EventFd").finish()
     }
 }
 
+#[cfg(feature = "tokio")]
 impl Stream for EventFd {
     type Item = Result<u64, EventFdError>;
 
@@ -85,30 +143,25 @@ impl Stream for EventFd {
     }
 }
 
+#[cfg(feature = "tokio")]
 impl AsRawFd for EventFd {
     fn as_raw_fd(&self) -> RawFd {
         self.evented.get_ref().inner.as_raw_fd()
     }
 }
 
+#[cfg(feature = "tokio")]
 impl EventFd {
     /// Create EventFd  with `init` permits.
     pub fn new(init: usize, semaphore: bool) -> Result<EventFd, EventFdError> {
-        let flags = if semaphore {
-            libc::O_CLOEXEC | libc::EFD_NONBLOCK | libc::EFD_SEMAPHORE
-        } else {
-            libc::O_CLOEXEC | libc::EFD_NONBLOCK
-        };
-
-        let fd = unsafe { eventfd(init as libc::c_uint, flags) };
-
-        if fd < 0 {
-            return Err(EventFdError::CreateError(io::Error::last_os_error()));
-        }
+        Self::from_fd(new_fd(init, semaphore)?)
+    }
 
+    /// Wrap the existing non-blocking eventfd
+    pub(crate) fn from_fd(fd: OwnedFd) -> Result<EventFd, EventFdError> {
         Ok(EventFd {
             evented: AsyncFd::new(EventFdInner {
-                inner: unsafe { File::from_raw_fd(fd) },
+                inner: File::from(fd),
             })
             .map_err(EventFdError::PollError)?,
         })
@@ -177,8 +230,19 @@ impl EventFd {
 
 #[cfg(test)]
 mod tests {
+    use std::os::unix::io::AsFd;
+
     use super::*;
 
+    #[test]
+    fn try_read_resets_counter() {
+        let fd = new_fd(3, false).unwrap();
+
+        assert_eq!(3, try_read(fd.as_fd()).unwrap());
+        assert_eq!(0, try_read(fd.as_fd()).unwrap());
+    }
+
+    #[cfg(feature = "tokio")]
     #[tokio::test]
     async fn non_semaphore() {
         let init = 5;
@@ -196,6 +260,7 @@ mod tests {
         assert_eq!(2 * increment, efd.recv().await.unwrap());
     }
 
+    #[cfg(feature = "tokio")]
     #[tokio::test]
     async fn semaphore() {
         let init = 2;
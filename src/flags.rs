@@ -3,7 +3,7 @@ use bitflags::bitflags;
 use crate::aio;
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     /// AIO write flags. See [`io_submit`](http://man7.org/linux/man-pages/man2/io_submit.2.html)
     pub struct WriteFlags: isize {
         /// Append data to the end of the file.  See the description
@@ -24,7 +24,8 @@ bitflags! {
         /// [`open(2)`]: http://man7.org/linux/man-pages/man2/open.2.html
         const DSYNC = aio::RWF_DSYNC as isize;
 
-        /// High priority request, poll if possible
+        /// High priority request, poll if possible. Dropped by kernel AIO and `IoUring`,
+        /// see [`CompletionMode::Polled`](enum.CompletionMode.html#variant.Polled)
         const HIPRI = aio::RWF_HIPRI as isize;
 
         /// Don't wait if the I/O will block for operations such as
@@ -47,10 +48,11 @@ bitflags! {
 }
 
 bitflags! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
     /// AIO read flags. See [`io_submit`](http://man7.org/linux/man-pages/man2/io_submit.2.html)
     pub struct ReadFlags: isize {
-        /// High priority request, poll if possible
+        /// High priority request, poll if possible. Dropped by kernel AIO and `IoUring`,
+        /// see [`CompletionMode::Polled`](enum.CompletionMode.html#variant.Polled)
         const HIPRI = aio::RWF_HIPRI as isize;
 
         /// Don't wait if the I/O will block for operations such as
@@ -62,3 +64,30 @@ bitflags! {
         const NOWAIT = aio::RWF_NOWAIT as isize;
     }
 }
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    /// Poll events. See [`poll(2)`](http://man7.org/linux/man-pages/man2/poll.2.html)
+    pub struct PollFlags: libc::c_short {
+        /// There is data to read
+        const IN = libc::POLLIN;
+
+        /// There is some exceptional condition on the file descriptor
+        const PRI = libc::POLLPRI;
+
+        /// Writing is now possible
+        const OUT = libc::POLLOUT;
+
+        /// Stream socket peer closed connection, or shut down writing half of connection
+        const RDHUP = libc::POLLRDHUP;
+
+        /// Error condition. Always reported, even if not requested
+        const ERR = libc::POLLERR;
+
+        /// Hang up. Always reported, even if not requested
+        const HUP = libc::POLLHUP;
+
+        /// Invalid request: fd not open
+        const NVAL = libc::POLLNVAL;
+    }
+}
@@ -0,0 +1,123 @@
+use std::os::unix::prelude::*;
+use std::{fs, io, mem};
+
+/// Logical block size of most block devices
+const DEFAULT_BLOCK_SIZE: u64 = 512;
+
+/// Alignment restrictions for `O_DIRECT` I/O on the file
+///
+/// Discovered with `statx` and `STATX_DIOALIGN` (Linux 6.1+). On older kernels,
+/// the logical block size of the block device (`BLKSSZGET`), or of the device, which
+/// holds the file, is used for both restrictions. If it's unknown, 512 bytes is assumed.
+/// The preferred I/O block size of the file (`st_blksize`) is only a hint, which may
+/// lower the assumed size, since it's usually larger than the actual restriction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DioAlignment {
+    /// Required alignment of the buffer address
+    pub memory: u64,
+    /// Required alignment of the file offset and the length of I/O
+    pub offset: u64,
+}
+
+impl DioAlignment {
+    pub(crate) fn discover(fd: RawFd) -> io::Result<DioAlignment> {
+        if let Some(alignment) = Self::from_statx(fd) {
+            return Ok(alignment);
+        }
+
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let block_size = if stat.st_mode & libc::S_IFMT == libc::S_IFBLK {
+            let mut logical_block_size: libc::c_int = 0;
+            if unsafe { libc::ioctl(fd, libc::BLKSSZGET, &mut logical_block_size) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            logical_block_size as u64
+        } else {
+            Self::fallback_block_size(&stat)
+        };
+
+        Ok(DioAlignment {
+            memory: block_size,
+            offset: block_size,
+        })
+    }
+
+    /// Logical block size of the device, which holds the file, or 512 bytes
+    fn fallback_block_size(stat: &libc::stat) -> u64 {
+        if let Some(block_size) = device_logical_block_size(stat.st_dev) {
+            return block_size;
+        }
+
+        let hint = stat.st_blksize as u64;
+        if hint.is_power_of_two() {
+            hint.min(DEFAULT_BLOCK_SIZE)
+        } else {
+            DEFAULT_BLOCK_SIZE
+        }
+    }
+
+    fn from_statx(fd: RawFd) -> Option<DioAlignment> {
+        let mut stx: libc::statx = unsafe { mem::zeroed() };
+
+        let res = unsafe {
+            libc::statx(
+                fd,
+                c"".as_ptr(),
+                libc::AT_EMPTY_PATH,
+                libc::STATX_DIOALIGN,
+                &mut stx,
+            )
+        };
+
+        // zero offset alignment means that direct I/O is not supported by the file
+        if res != 0 || stx.stx_mask & libc::STATX_DIOALIGN == 0 || stx.stx_dio_offset_align == 0 {
+            return None;
+        }
+
+        Some(DioAlignment {
+            memory: stx.stx_dio_mem_align as u64,
+            offset: stx.stx_dio_offset_align as u64,
+        })
+    }
+}
+
+/// Logical block size of the block device from sysfs. Partitions have no queue
+/// of their own, so the queue of the whole disk is checked too
+fn device_logical_block_size(dev: libc::dev_t) -> Option<u64> {
+    let device = format!("/sys/dev/block/{}:{}", libc::major(dev), libc::minor(dev));
+
+    ["queue", "../queue"].iter().find_map(|queue| {
+        fs::read_to_string(format!("{device}/{queue}/logical_block_size"))
+            .ok()?
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .filter(|block_size| block_size.is_power_of_two())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferred_block_size_not_required() {
+        let mut stat: libc::stat = unsafe { mem::zeroed() };
+        stat.st_mode = libc::S_IFREG;
+        // not a block device, as for tmpfs or network filesystems
+        stat.st_dev = libc::makedev(0, u32::MAX >> 12);
+
+        stat.st_blksize = 65536;
+        assert_eq!(512, DioAlignment::fallback_block_size(&stat));
+
+        stat.st_blksize = 256;
+        assert_eq!(256, DioAlignment::fallback_block_size(&stat));
+
+        stat.st_blksize = 0;
+        assert_eq!(512, DioAlignment::fallback_block_size(&stat));
+    }
+}
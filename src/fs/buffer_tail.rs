@@ -0,0 +1,71 @@
+use std::{fmt, slice};
+
+use crate::AioBuffer;
+use crate::locked_buf::{LifetimeExtender, private};
+
+/// Rest of the buffer from `start`, to resume the transfer after a short read or write
+pub(crate) struct BufferTail<'a> {
+    buffer: &'a dyn AioBuffer,
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl<'a> BufferTail<'a> {
+    pub(crate) fn new(buffer: &'a mut dyn AioBuffer, start: usize) -> Self {
+        let tail = &mut buffer.as_mut()[start..];
+        let (ptr, len) = (tail.as_mut_ptr(), tail.len());
+
+        BufferTail { buffer, ptr, len }
+    }
+
+    /// Tail of the buffer, which is only written from
+    ///
+    /// # Safety
+    ///
+    /// `as_mut` must not be called on the tail
+    pub(crate) unsafe fn new_shared(buffer: &'a dyn AioBuffer, start: usize) -> Self {
+        let tail = &buffer.as_ref()[start..];
+
+        BufferTail {
+            buffer,
+            ptr: tail.as_ptr() as *mut u8,
+            len: tail.len(),
+        }
+    }
+}
+
+impl fmt::Debug for BufferTail<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BufferTail")
+            .field("buffer", &self.buffer)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl AsRef<[u8]> for BufferTail<'_> {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl AsMut<[u8]> for BufferTail<'_> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl private::Sealed for BufferTail<'_> {
+    fn aio_addr_and_len(&self) -> (u64, u64) {
+        (self.ptr as usize as u64, self.len as u64)
+    }
+
+    fn lifetime_extender(&self) -> LifetimeExtender {
+        self.buffer.lifetime_extender()
+    }
+}
+
+impl AioBuffer for BufferTail<'_> {}
+
+unsafe impl Send for BufferTail<'_> {}
+unsafe impl Sync for BufferTail<'_> {}
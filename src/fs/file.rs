@@ -3,13 +3,12 @@ use std::os::unix::prelude::*;
 use std::path::{Path, PathBuf};
 use std::{fmt, io};
 
-use intrusive_collections::DefaultLinkOps;
-use intrusive_collections::linked_list::LinkedListOps;
-use lock_api::RawMutex;
+use tokio::time::Instant;
 
 use crate::errors::AioCommandError;
-use crate::fs::AioOpenOptionsExt;
-use crate::{GenericAioContextHandle, LockedBuf, RawCommand, ReadFlags, WriteFlags};
+use crate::fs::buffer_tail::BufferTail;
+use crate::fs::{AioOpenOptionsExt, DioAlignment};
+use crate::{AioBuffer, AioLock, GenericAioContextHandle, RawCommand, ReadFlags, WriteFlags};
 
 /// AIO version of tokio [`File`], to work through [`GenericAioContextHandle`]
 ///
@@ -17,14 +16,42 @@ use crate::{GenericAioContextHandle, LockedBuf, RawCommand, ReadFlags, WriteFlag
 /// [`GenericAioContextHandle`]: struct.GenericAioContextHandle.html
 pub struct File {
     pub(crate) inner: tokio::fs::File,
+    pub(crate) dio_alignment: DioAlignment,
 }
 
 impl fmt::Debug for File {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.debug_struct("File").field("inner", &self.inner).finish()
+        f.debug_struct("File")
+            .field("inner", &self.inner)
+            .field("dio_alignment", &self.dio_alignment)
+            .finish()
     }
 }
 
+fn check_len(len: u64, buffer: &[u8]) -> Result<(), AioCommandError> {
+    if len > buffer.len() as u64 {
+        return Err(AioCommandError::InvalidLength {
+            len,
+            buffer_size: buffer.len() as u64,
+        });
+    }
+
+    Ok(())
+}
+
+/// Position to resume the transfer of `len` bytes from, after `transferred` bytes
+/// were transferred at `done`. None if the transfer made no progress
+fn resume_position(done: u64, transferred: u64, len: u64, alignment: u64) -> Option<u64> {
+    let end = done + transferred;
+    if end >= len {
+        return Some(len);
+    }
+
+    // the rest is resubmitted from the aligned position, transferring a few bytes again
+    let next = end - end % alignment;
+    (next > done).then_some(next)
+}
+
 impl File {
     /// Open the file. See tokio [`File::open`]
     ///
@@ -52,6 +79,42 @@ impl File {
         open_options.aio_open(path_buf, is_sync).await
     }
 
+    /// Alignment restrictions for I/O on the file, discovered when it was opened
+    ///
+    /// [`read_at`] and [`write_at`] reject misaligned requests with the `Misaligned` error
+    /// before submitting them to the kernel.
+    ///
+    /// [`read_at`]: struct.File.html#method.read_at
+    /// [`write_at`]: struct.File.html#method.write_at
+    pub fn dio_alignment(&self) -> DioAlignment {
+        self.dio_alignment
+    }
+
+    fn check_alignment(&self, offset: u64, len: u64, buffer: &[u8]) -> Result<(), AioCommandError> {
+        let DioAlignment {
+            memory,
+            offset: offset_alignment,
+        } = self.dio_alignment;
+
+        let checks = [
+            ("offset", offset, offset_alignment),
+            ("length", len, offset_alignment),
+            ("buffer address", buffer.as_ptr() as usize as u64, memory),
+        ];
+
+        for (what, value, alignment) in checks {
+            if !value.is_multiple_of(alignment) {
+                return Err(AioCommandError::Misaligned {
+                    what,
+                    value,
+                    alignment,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     /// Set file let. See tokio [`set_len`]
     ///
     /// [`set_len`]: ../tokio/fs/struct.File.html#method.set_len
@@ -78,24 +141,18 @@ impl File {
     /// See [`submit_request`] for more information
     ///
     /// [`submit_request`]: struct.GenericAioContextHandle.html#method.submit_request
-    /// [`buffer`]: struct.LockedBuf.html
+    /// [`buffer`]: trait.AioBuffer.html
     /// [`flags`]: struct.ReadFlags.html
-    pub async fn read_at<
-        M: RawMutex,
-        A: crate::IntrusiveAdapter<M, L>,
-        L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-    >(
+    pub async fn read_at<M: AioLock>(
         &self,
-        aio_handle: &GenericAioContextHandle<M, A, L>,
+        aio_handle: &GenericAioContextHandle<M>,
         offset: u64,
-        buffer: &mut LockedBuf,
+        buffer: &mut impl AioBuffer,
         len: u64,
         flags: ReadFlags,
-    ) -> Result<u64, AioCommandError>
-    where
-        A::LinkOps: LinkedListOps + Default,
-    {
-        assert!(len <= buffer.size() as u64);
+    ) -> Result<u64, AioCommandError> {
+        check_len(len, buffer.as_ref())?;
+        self.check_alignment(offset, len, buffer.as_ref())?;
         aio_handle
             .submit_request(
                 self,
@@ -114,24 +171,18 @@ impl File {
     /// See [`submit_request`] for more information
     ///
     /// [`submit_request`]: struct.GenericAioContextHandle.html#method.submit_request
-    /// [`buffer`]: struct.LockedBuf.html
+    /// [`buffer`]: trait.AioBuffer.html
     /// [`flags`]: struct.ReadFlags.html
-    pub async fn write_at<
-        M: RawMutex,
-        A: crate::IntrusiveAdapter<M, L>,
-        L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-    >(
+    pub async fn write_at<M: AioLock>(
         &self,
-        aio_handle: &GenericAioContextHandle<M, A, L>,
+        aio_handle: &GenericAioContextHandle<M>,
         offset: u64,
-        buffer: &LockedBuf,
+        buffer: &impl AioBuffer,
         len: u64,
         flags: WriteFlags,
-    ) -> Result<u64, AioCommandError>
-    where
-        A::LinkOps: LinkedListOps + Default,
-    {
-        assert!(len <= buffer.size() as u64);
+    ) -> Result<u64, AioCommandError> {
+        check_len(len, buffer.as_ref())?;
+        self.check_alignment(offset, len, buffer.as_ref())?;
         aio_handle
             .submit_request(
                 self,
@@ -145,22 +196,238 @@ impl File {
             .await
     }
 
+    /// Read exactly `len` bytes of the file at `offset` to the [`buffer`]
+    ///
+    /// Short reads are resumed from the last aligned position. If the file ends before
+    /// `len` bytes are read, the `UnexpectedEof` error is returned.
+    ///
+    /// [`buffer`]: trait.AioBuffer.html
+    pub async fn read_exact_at<M: AioLock>(
+        &self,
+        aio_handle: &GenericAioContextHandle<M>,
+        offset: u64,
+        buffer: &mut impl AioBuffer,
+        len: u64,
+        flags: ReadFlags,
+    ) -> Result<(), AioCommandError> {
+        check_len(len, buffer.as_ref())?;
+        self.check_alignment(offset, len, buffer.as_ref())?;
+
+        let mut done = 0;
+        while done < len {
+            let mut tail = BufferTail::new(buffer, done as usize);
+            let transferred = aio_handle
+                .submit_request(
+                    self,
+                    RawCommand::Pread {
+                        offset: offset + done,
+                        buffer: &mut tail,
+                        flags,
+                        len: len - done,
+                    },
+                )
+                .await?;
+
+            done = resume_position(done, transferred, len, self.dio_alignment.offset).ok_or(
+                AioCommandError::UnexpectedEof {
+                    len,
+                    transferred: done + transferred,
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Write exactly `len` bytes from the [`buffer`] to the file at `offset`
+    ///
+    /// Short writes are resumed from the last aligned position. If the kernel
+    /// stops writing, the `WriteZero` error is returned.
+    ///
+    /// [`buffer`]: trait.AioBuffer.html
+    pub async fn write_all_at<M: AioLock>(
+        &self,
+        aio_handle: &GenericAioContextHandle<M>,
+        offset: u64,
+        buffer: &impl AioBuffer,
+        len: u64,
+        flags: WriteFlags,
+    ) -> Result<(), AioCommandError> {
+        check_len(len, buffer.as_ref())?;
+        self.check_alignment(offset, len, buffer.as_ref())?;
+
+        let mut done = 0;
+        while done < len {
+            // the command only reads from the tail
+            let tail = unsafe { BufferTail::new_shared(buffer, done as usize) };
+            let transferred = aio_handle
+                .submit_request(
+                    self,
+                    RawCommand::Pwrite {
+                        offset: offset + done,
+                        buffer: &tail,
+                        flags,
+                        len: len - done,
+                    },
+                )
+                .await?;
+
+            done = resume_position(done, transferred, len, self.dio_alignment.offset).ok_or(
+                AioCommandError::WriteZero {
+                    len,
+                    transferred: done + transferred,
+                },
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`read_at`], but gives up at `deadline`.
+    ///
+    /// See [`submit_request_with_deadline`] for more information
+    ///
+    /// [`read_at`]: struct.File.html#method.read_at
+    /// [`submit_request_with_deadline`]: struct.GenericAioContextHandle.html#method.submit_request_with_deadline
+    pub async fn read_at_with_deadline<M: AioLock>(
+        &self,
+        aio_handle: &GenericAioContextHandle<M>,
+        offset: u64,
+        buffer: &mut impl AioBuffer,
+        len: u64,
+        flags: ReadFlags,
+        deadline: Instant,
+    ) -> Result<u64, AioCommandError> {
+        check_len(len, buffer.as_ref())?;
+        self.check_alignment(offset, len, buffer.as_ref())?;
+        aio_handle
+            .submit_request_with_deadline(
+                self,
+                RawCommand::Pread {
+                    offset,
+                    buffer,
+                    flags,
+                    len,
+                },
+                deadline,
+            )
+            .await
+    }
+
+    /// Same as [`write_at`], but gives up at `deadline`.
+    ///
+    /// See [`submit_request_with_deadline`] for more information
+    ///
+    /// [`write_at`]: struct.File.html#method.write_at
+    /// [`submit_request_with_deadline`]: struct.GenericAioContextHandle.html#method.submit_request_with_deadline
+    pub async fn write_at_with_deadline<M: AioLock>(
+        &self,
+        aio_handle: &GenericAioContextHandle<M>,
+        offset: u64,
+        buffer: &impl AioBuffer,
+        len: u64,
+        flags: WriteFlags,
+        deadline: Instant,
+    ) -> Result<u64, AioCommandError> {
+        check_len(len, buffer.as_ref())?;
+        self.check_alignment(offset, len, buffer.as_ref())?;
+        aio_handle
+            .submit_request_with_deadline(
+                self,
+                RawCommand::Pwrite {
+                    offset,
+                    buffer,
+                    flags,
+                    len,
+                },
+                deadline,
+            )
+            .await
+    }
+
+    /// Read the file through AIO at `offset` into the [`buffers`] with provided [`flags`].
+    ///
+    /// The buffers are filled in order, each one to its full size. Returns the total
+    /// number of bytes read.
+    ///
+    /// See [`submit_request`] for more information
+    ///
+    /// [`submit_request`]: struct.GenericAioContextHandle.html#method.submit_request
+    /// [`buffers`]: trait.AioBuffer.html
+    /// [`flags`]: struct.ReadFlags.html
+    pub async fn read_vectored_at<M: AioLock>(
+        &self,
+        aio_handle: &GenericAioContextHandle<M>,
+        offset: u64,
+        buffers: &mut [impl AioBuffer],
+        flags: ReadFlags,
+    ) -> Result<u64, AioCommandError> {
+        for buffer in buffers.iter() {
+            let buffer = buffer.as_ref();
+            self.check_alignment(offset, buffer.len() as u64, buffer)?;
+        }
+        let mut buffers: Vec<&mut dyn AioBuffer> = buffers
+            .iter_mut()
+            .map(|buffer| buffer as &mut dyn AioBuffer)
+            .collect();
+        aio_handle
+            .submit_request(
+                self,
+                RawCommand::Preadv {
+                    offset,
+                    buffers: &mut buffers,
+                    flags,
+                },
+            )
+            .await
+    }
+
+    /// Write to the file through AIO at `offset` from the [`buffers`] with provided [`flags`].
+    ///
+    /// The buffers are written in order, each one in its full size. Returns the total
+    /// number of bytes written.
+    ///
+    /// See [`submit_request`] for more information
+    ///
+    /// [`submit_request`]: struct.GenericAioContextHandle.html#method.submit_request
+    /// [`buffers`]: trait.AioBuffer.html
+    /// [`flags`]: struct.WriteFlags.html
+    pub async fn write_vectored_at<M: AioLock>(
+        &self,
+        aio_handle: &GenericAioContextHandle<M>,
+        offset: u64,
+        buffers: &[impl AioBuffer],
+        flags: WriteFlags,
+    ) -> Result<u64, AioCommandError> {
+        for buffer in buffers {
+            let buffer = buffer.as_ref();
+            self.check_alignment(offset, buffer.len() as u64, buffer)?;
+        }
+        let buffers: Vec<&dyn AioBuffer> = buffers
+            .iter()
+            .map(|buffer| buffer as &dyn AioBuffer)
+            .collect();
+        aio_handle
+            .submit_request(
+                self,
+                RawCommand::Pwritev {
+                    offset,
+                    buffers: &buffers,
+                    flags,
+                },
+            )
+            .await
+    }
+
     /// Sync data and metadata through AIO
     ///
     /// See [`submit_request`] for more information
     ///
     /// [`submit_request`]: struct.GenericAioContextHandle.html#method.submit_request
-    pub async fn sync_all<
-        M: RawMutex,
-        A: crate::IntrusiveAdapter<M, L>,
-        L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-    >(
+    pub async fn sync_all<M: AioLock>(
         &self,
-        aio_handle: &GenericAioContextHandle<M, A, L>,
-    ) -> Result<(), AioCommandError>
-    where
-        A::LinkOps: LinkedListOps + Default,
-    {
+        aio_handle: &GenericAioContextHandle<M>,
+    ) -> Result<(), AioCommandError> {
         let r = aio_handle.submit_request(self, RawCommand::Fsync).await?;
         if r != 0 {
             return Err(AioCommandError::NonZeroCode);
@@ -173,17 +440,10 @@ impl File {
     /// See [`submit_request`] for more information
     ///
     /// [`submit_request`]: struct.GenericAioContextHandle.html#method.submit_request
-    pub async fn sync_data<
-        M: RawMutex,
-        A: crate::IntrusiveAdapter<M, L>,
-        L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-    >(
+    pub async fn sync_data<M: AioLock>(
         &self,
-        aio_handle: &GenericAioContextHandle<M, A, L>,
-    ) -> Result<(), AioCommandError>
-    where
-        A::LinkOps: LinkedListOps + Default,
-    {
+        aio_handle: &GenericAioContextHandle<M>,
+    ) -> Result<(), AioCommandError> {
         let r = aio_handle.submit_request(self, RawCommand::Fdsync).await?;
         if r != 0 {
             return Err(AioCommandError::NonZeroCode);
@@ -1,5 +1,14 @@
+//! Files, accessed through AIO, and whole-file helpers
+
+mod alignment;
+mod buffer_tail;
 mod file;
 mod open_options;
+mod stream;
+mod whole_file;
 
+pub use alignment::DioAlignment;
 pub use file::File;
 pub use open_options::AioOpenOptionsExt;
+pub use stream::FileStream;
+pub use whole_file::{read, write};
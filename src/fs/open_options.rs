@@ -3,6 +3,8 @@ use std::io;
 use std::os::unix::prelude::*;
 use std::path::PathBuf;
 
+use crate::fs::DioAlignment;
+
 /// Extension trait to [`OpenOptions`] to support opening files
 /// in AIO mode
 ///
@@ -28,6 +30,14 @@ impl AioOpenOptionsExt for OpenOptions {
 
         let tokio_file = tokio::fs::OpenOptions::from(self).open(path).await?;
 
-        Ok(crate::fs::File { inner: tokio_file })
+        let fd = tokio_file.as_raw_fd();
+        let dio_alignment = tokio::task::spawn_blocking(move || DioAlignment::discover(fd))
+            .await
+            .map_err(io::Error::other)??;
+
+        Ok(crate::fs::File {
+            inner: tokio_file,
+            dio_alignment,
+        })
     }
 }
@@ -0,0 +1,350 @@
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::os::unix::prelude::*;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+use std::{cmp, fmt};
+
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use crate::{AioContextHandle, File, LockedBuf, ReadFlags, WriteFlags};
+
+type PendingIo = Pin<Box<dyn Future<Output = (LockedBuf, io::Result<u64>)> + Send>>;
+
+enum Pending {
+    Load { offset: u64, io: PendingIo },
+    Flush { io: PendingIo },
+}
+
+/// Buffered stream over AIO [`File`], which implements tokio [`AsyncRead`],
+/// [`AsyncWrite`] and [`AsyncSeek`]
+///
+/// The stream keeps a window of the file in the aligned [`LockedBuf`], so arbitrary
+/// unaligned reads and writes are served from the window, while the file is accessed
+/// with aligned `O_DIRECT` I/O only. Written data stays in the window until it is
+/// flushed, so [`flush`] or [`shutdown`] must be called before the stream is dropped.
+///
+/// [`File`]: struct.File.html
+/// [`LockedBuf`]: struct.LockedBuf.html
+/// [`AsyncRead`]: ../tokio/io/trait.AsyncRead.html
+/// [`AsyncWrite`]: ../tokio/io/trait.AsyncWrite.html
+/// [`AsyncSeek`]: ../tokio/io/trait.AsyncSeek.html
+/// [`flush`]: ../tokio/io/trait.AsyncWriteExt.html#method.flush
+/// [`shutdown`]: ../tokio/io/trait.AsyncWriteExt.html#method.shutdown
+pub struct FileStream {
+    file: Arc<File>,
+    aio_handle: AioContextHandle,
+    alignment: u64,
+    buffer: Option<LockedBuf>,
+    /// File offset of the window, if loaded
+    window: Option<u64>,
+    /// Number of bytes in the window, which hold the file data
+    valid: usize,
+    /// End of the window part, modified since it was loaded
+    dirty_end: Option<usize>,
+    /// Length of the file, including unflushed data
+    len: u64,
+    pos: u64,
+    pending: Option<Pending>,
+}
+
+impl fmt::Debug for FileStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FileStream")
+            .field("file", &self.file)
+            .field("window", &self.window)
+            .field("len", &self.len)
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+fn align_down(value: u64, alignment: u64) -> u64 {
+    value / alignment * alignment
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+impl FileStream {
+    /// Create the stream over the `file`, opened with [`aio_open`], with the window of
+    /// `buffer_size` bytes. The size is rounded up to the `O_DIRECT` alignment of the file.
+    ///
+    /// [`aio_open`]: trait.AioOpenOptionsExt.html#tymethod.aio_open
+    pub async fn new(
+        file: File,
+        aio_handle: AioContextHandle,
+        buffer_size: usize,
+    ) -> io::Result<FileStream> {
+        let metadata = file.metadata().await?;
+        let alignment = file.dio_alignment().offset;
+
+        let buffer = LockedBuf::with_size(align_up(buffer_size as u64, alignment) as usize)
+            .map_err(io::Error::other)?;
+
+        Ok(FileStream {
+            file: Arc::new(file),
+            aio_handle,
+            alignment,
+            buffer: Some(buffer),
+            window: None,
+            valid: 0,
+            dirty_end: None,
+            len: metadata.len(),
+            pos: 0,
+            pending: None,
+        })
+    }
+
+    /// Reference to the underlying file
+    pub fn get_ref(&self) -> &File {
+        &self.file
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer
+            .as_ref()
+            .expect("buffer is in use by pending I/O")
+            .size()
+    }
+
+    /// Range of the file, which may be served from the window
+    fn window_contains(&self, pos: u64, len: usize) -> bool {
+        match self.window {
+            Some(offset) => pos >= offset && pos < offset + len as u64,
+            None => false,
+        }
+    }
+
+    fn start_load(&mut self, offset: u64) {
+        let mut buffer = self.buffer.take().expect("buffer is in use by pending I/O");
+
+        if offset >= self.len {
+            // nothing to read beyond the end of file
+            buffer.as_mut().fill(0);
+            self.window = Some(offset);
+            self.valid = 0;
+            self.buffer = Some(buffer);
+            return;
+        }
+
+        let file = self.file.clone();
+        let aio_handle = self.aio_handle.clone();
+
+        self.pending = Some(Pending::Load {
+            offset,
+            io: Box::pin(async move {
+                let len = buffer.size() as u64;
+                let res = file
+                    .read_at(&aio_handle, offset, &mut buffer, len, ReadFlags::empty())
+                    .await
+                    .map_err(io::Error::from);
+                (buffer, res)
+            }),
+        });
+    }
+
+    fn start_flush(&mut self) {
+        let dirty_end = self.dirty_end.expect("no dirty data to flush");
+        let window = self.window.expect("no window to flush");
+
+        // LockedBuf is always written from its start, so the clean prefix of the window
+        // is written as well
+        let end = align_up(dirty_end as u64, self.alignment);
+        let file_len = self.len;
+
+        let buffer = self.buffer.take().expect("buffer is in use by pending I/O");
+        let file = self.file.clone();
+        let aio_handle = self.aio_handle.clone();
+
+        self.pending = Some(Pending::Flush {
+            io: Box::pin(async move {
+                let res = file
+                    .write_at(&aio_handle, window, &buffer, end, WriteFlags::empty())
+                    .await
+                    .map_err(io::Error::from)
+                    .and_then(|written| {
+                        if written != end {
+                            return Err(io::Error::new(
+                                io::ErrorKind::WriteZero,
+                                "short write while flushing the window",
+                            ));
+                        }
+
+                        // the tail block was padded with zeroes beyond the end of file
+                        if window + end > file_len
+                            && unsafe { libc::ftruncate(file.as_raw_fd(), file_len as _) } != 0
+                        {
+                            return Err(io::Error::last_os_error());
+                        }
+
+                        Ok(written)
+                    });
+                (buffer, res)
+            }),
+        });
+    }
+
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let (buffer, res) = match &mut self.pending {
+            Some(Pending::Load { io, .. }) | Some(Pending::Flush { io }) => {
+                ready!(io.as_mut().poll(cx))
+            }
+            None => return Poll::Ready(Ok(())),
+        };
+
+        let pending = self.pending.take().unwrap();
+        self.buffer = Some(buffer);
+
+        let bytes = match res {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                if let Pending::Load { .. } = pending {
+                    // the window content is partially overwritten
+                    self.window = None;
+                }
+                return Poll::Ready(Err(e));
+            }
+        };
+
+        match pending {
+            Pending::Load { offset, .. } => {
+                let valid = bytes as usize;
+                self.buffer.as_mut().unwrap().as_mut()[valid..].fill(0);
+                self.window = Some(offset);
+                self.valid = valid;
+
+                if valid < self.buffer_size() {
+                    // short read means that the file ends inside the window
+                    self.len = offset + bytes;
+                }
+            }
+            Pending::Flush { .. } => {
+                self.dirty_end = None;
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Replace the window with the one, containing `pos`
+    fn start_moving_window(&mut self, pos: u64) {
+        if self.dirty_end.is_some() {
+            self.start_flush();
+        } else {
+            self.start_load(align_down(pos, self.alignment));
+        }
+    }
+}
+
+impl AsyncRead for FileStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            ready!(this.poll_pending(cx))?;
+
+            if this.pos >= this.len || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.window_contains(this.pos, this.valid) {
+                let start = (this.pos - this.window.unwrap()) as usize;
+                let n = cmp::min(buf.remaining(), this.valid - start);
+
+                buf.put_slice(&this.buffer.as_ref().unwrap().as_ref()[start..start + n]);
+                this.pos += n as u64;
+
+                return Poll::Ready(Ok(()));
+            }
+
+            this.start_moving_window(this.pos);
+        }
+    }
+}
+
+impl AsyncWrite for FileStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        loop {
+            ready!(this.poll_pending(cx))?;
+
+            if data.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            let buffer_size = this.buffer_size();
+
+            if this.window_contains(this.pos, buffer_size) {
+                let start = (this.pos - this.window.unwrap()) as usize;
+                let n = cmp::min(data.len(), buffer_size - start);
+
+                this.buffer.as_mut().unwrap().as_mut()[start..start + n]
+                    .copy_from_slice(&data[..n]);
+
+                this.dirty_end = Some(cmp::max(this.dirty_end.unwrap_or(0), start + n));
+                this.valid = cmp::max(this.valid, start + n);
+                this.pos += n as u64;
+                this.len = cmp::max(this.len, this.pos);
+
+                return Poll::Ready(Ok(n));
+            }
+
+            this.start_moving_window(this.pos);
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            ready!(this.poll_pending(cx))?;
+
+            if this.dirty_end.is_none() {
+                return Poll::Ready(Ok(()));
+            }
+
+            this.start_flush();
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl AsyncSeek for FileStream {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+
+        let pos = match position {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => this.len.checked_add_signed(delta),
+            SeekFrom::Current(delta) => this.pos.checked_add_signed(delta),
+        };
+
+        this.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
@@ -0,0 +1,170 @@
+use std::io;
+use std::path::Path;
+
+use crate::errors::AioCommandError;
+use crate::{AioLock, File, GenericAioContextHandle, LockedBuf, RawCommand, ReadFlags, WriteFlags};
+
+/// Size of the part of the file, read or written with a single request
+const CHUNK_SIZE: u64 = 128 * 1024;
+
+/// Max number of chunk requests in flight at once
+const MAX_IN_FLIGHT: usize = 4;
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+/// Chunk size, suitable for the file, and the buffers for the requests in flight,
+/// to transfer `len` bytes
+fn chunk_buffers<M: AioLock>(
+    file: &File,
+    aio_handle: &GenericAioContextHandle<M>,
+    len: u64,
+) -> io::Result<(u64, Vec<LockedBuf>)> {
+    let chunk_size = align_up(CHUNK_SIZE, file.dio_alignment().offset);
+
+    let num_slots = aio_handle
+        .num_slots()
+        .ok_or(io::Error::from(AioCommandError::AioStopped))?;
+    let in_flight = len
+        .div_ceil(chunk_size)
+        .clamp(1, MAX_IN_FLIGHT.min(num_slots) as u64) as usize;
+
+    let buffers = (0..in_flight)
+        .map(|_| LockedBuf::with_size(chunk_size as usize))
+        .collect::<Result<_, _>>()
+        .map_err(io::Error::other)?;
+
+    Ok((chunk_size, buffers))
+}
+
+impl File {
+    /// Read the whole file through AIO, from the start to the end
+    ///
+    /// The file is read in aligned chunks, several of them in flight at once.
+    pub async fn read_to_end<M: AioLock>(
+        &self,
+        aio_handle: &GenericAioContextHandle<M>,
+    ) -> io::Result<Vec<u8>> {
+        let size_hint = self.metadata().await?.len();
+        let (chunk_size, mut buffers) = chunk_buffers(self, aio_handle, size_hint)?;
+
+        let mut data = Vec::with_capacity(size_hint as usize);
+        let mut offset = 0;
+
+        loop {
+            // the last chunk is read in full, so the length is rounded up to the alignment.
+            // At least one chunk is read to find the end of file, if it has grown
+            let num_chunks = (size_hint.saturating_sub(offset).div_ceil(chunk_size) as usize)
+                .clamp(1, buffers.len());
+
+            let futures = aio_handle
+                .submit_batch(buffers[..num_chunks].iter_mut().enumerate().map(
+                    |(index, buffer)| {
+                        (
+                            self,
+                            RawCommand::Pread {
+                                offset: offset + index as u64 * chunk_size,
+                                buffer,
+                                len: chunk_size,
+                                flags: ReadFlags::empty(),
+                            },
+                        )
+                    },
+                ))
+                .await?;
+
+            let mut sizes = Vec::with_capacity(num_chunks);
+            for future in futures {
+                sizes.push(future.await?);
+            }
+
+            for (buffer, size) in buffers.iter().zip(sizes) {
+                data.extend_from_slice(&buffer.as_ref()[..size as usize]);
+
+                // short read means the end of file
+                if size < chunk_size {
+                    return Ok(data);
+                }
+            }
+
+            offset += num_chunks as u64 * chunk_size;
+        }
+    }
+}
+
+/// Read the entire contents of the file at `path` through AIO. See [`read_to_end`]
+///
+/// [`read_to_end`]: ../struct.File.html#method.read_to_end
+pub async fn read<M: AioLock>(
+    path: impl AsRef<Path>,
+    aio_handle: &GenericAioContextHandle<M>,
+) -> io::Result<Vec<u8>> {
+    File::open(path, false).await?.read_to_end(aio_handle).await
+}
+
+/// Write `data` as the entire contents of the file at `path` through AIO
+///
+/// The file is created if it doesn't exist, and truncated otherwise. The data is
+/// written in aligned chunks, several of them in flight at once. The last chunk
+/// is padded with zeroes up to the alignment, and the padding is truncated afterwards.
+pub async fn write<M: AioLock>(
+    path: impl AsRef<Path>,
+    aio_handle: &GenericAioContextHandle<M>,
+    data: impl AsRef<[u8]>,
+) -> io::Result<()> {
+    let data = data.as_ref();
+    let mut file = File::create(path, false).await?;
+
+    let alignment = file.dio_alignment().offset;
+    let (chunk_size, mut buffers) = chunk_buffers(&file, aio_handle, data.len() as u64)?;
+
+    let chunks: Vec<_> = data.chunks(chunk_size as usize).collect();
+    let mut offset = 0;
+
+    for wave in chunks.chunks(buffers.len()) {
+        let mut lens = Vec::with_capacity(wave.len());
+        for (buffer, chunk) in buffers.iter_mut().zip(wave) {
+            let len = align_up(chunk.len() as u64, alignment);
+            let buffer = &mut buffer.as_mut()[..len as usize];
+
+            buffer[..chunk.len()].copy_from_slice(chunk);
+            buffer[chunk.len()..].fill(0);
+            lens.push(len);
+        }
+
+        let futures =
+            aio_handle
+                .submit_batch(buffers.iter().zip(&lens).enumerate().map(
+                    |(index, (buffer, &len))| {
+                        (
+                            &file,
+                            RawCommand::Pwrite {
+                                offset: offset + index as u64 * chunk_size,
+                                buffer,
+                                len,
+                                flags: WriteFlags::empty(),
+                            },
+                        )
+                    },
+                ))
+                .await?;
+
+        for (future, len) in futures.into_iter().zip(&lens) {
+            if future.await? != *len {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "short write while writing the file",
+                ));
+            }
+        }
+
+        offset += wave.len() as u64 * chunk_size;
+    }
+
+    if !(data.len() as u64).is_multiple_of(alignment) {
+        file.set_len(data.len() as u64).await?;
+    }
+
+    Ok(())
+}
@@ -12,84 +12,111 @@
 //! calls to perform file I/O. [Linux kernel-level AIO](http://lse.sourceforge.net/io/aio.html), on the
 //! other hand, provides kernel-level asynchronous scheduling of I/O operations to the underlying block device.
 
-use std::convert::TryInto;
+#[cfg(feature = "tokio")]
 use std::future::Future;
 use std::os::unix::prelude::*;
-use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 use std::{fmt, io, mem};
 
-use intrusive_collections::linked_list::LinkedListOps;
-use intrusive_collections::{DefaultLinkOps, linked_list};
-use lock_api::{Mutex, RawMutex};
-use tokio::sync::{Semaphore, oneshot};
-use tokio::task;
+use lock_api::Mutex;
+use tokio::sync::{Notify, Semaphore, oneshot};
+use tokio::time::{self, Instant};
 
+use backend::Backend;
 pub use commands::*;
+#[cfg(feature = "tokio")]
+pub use context_pool::{AioContextPool, AioContextPoolHandle, ShardRouting};
 pub use errors::{AioCommandError, AioContextError};
+#[cfg(feature = "tokio")]
 pub use eventfd::EventFd;
 pub use flags::*;
-pub use fs::{AioOpenOptionsExt, File};
-pub use locked_buf::{LockedBuf, LockedBufError};
+#[cfg(feature = "tokio")]
+pub use fs::{AioOpenOptionsExt, DioAlignment, File, FileStream};
+pub use locked_buf::{
+    AioBuffer, LOCKED_BUF_SLICE_ALIGNMENT, LockedBuf, LockedBufError, LockedBufSlice,
+};
+pub use locked_buf_pool::{LockedBufPool, LockedBufPoolStats};
 pub use noop_lock::NoopLock;
-use requests::{Request, Requests};
-use wait_future::AioWaitFuture;
+pub use options::{AioBackend, AioContextOptions, AioFallback, CompletionMode};
+pub use reaper::{AioReaper, GenericAioReaper, LocalAioReaper};
+pub use requests::AioLock;
+use requests::{Completion, Request, Requests};
+#[cfg(feature = "tokio")]
+use ring::AioRing;
+use stats::StatsCounters;
+pub use stats::{AioOpcode, AioOpcodeStats, AioStats, LatencyHistogram};
+pub use wait_future::AioRequestFuture;
+use wait_future::{AioWaitFuture, io_cancel_nowait};
 
-pub use crate::requests::AtomicLink;
-pub use crate::requests::IntrusiveAdapter;
-pub use crate::requests::{LocalRequestAdapter, SyncRequestAdapter};
+#[macro_use]
+mod trace;
 
 mod aio;
+mod backend;
 mod commands;
+#[cfg(feature = "tokio")]
+mod context_pool;
 mod errors;
 mod eventfd;
 mod flags;
-mod fs;
+#[cfg(feature = "tokio")]
+pub mod fs;
 mod locked_buf;
+mod locked_buf_pool;
 mod noop_lock;
+mod options;
+mod reaper;
 mod requests;
+#[cfg(feature = "tokio")]
+mod ring;
+mod stats;
+mod thread_pool;
+mod uring;
 mod wait_future;
 
 type AioResult = aio::__s64;
 
-pub(crate) struct GenericAioContextInner<
-    M: RawMutex,
-    A: crate::IntrusiveAdapter<M, L>,
-    L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-> where
-    A::LinkOps: LinkedListOps + Default,
-{
-    context: aio::aio_context_t,
-    eventfd: RawFd,
+pub(crate) struct GenericAioContextInner<M: AioLock> {
+    backend: Backend<M>,
+    /// Why the requested backend is not used
+    fallback: Option<AioFallback>,
+    eventfd: OwnedFd,
     num_slots: usize,
     capacity: Option<Arc<Semaphore>>,
-    requests: Mutex<M, Requests<M, A, L>>,
+    requests: Mutex<M, Requests>,
+    /// Slab of all requests, indexed by the slot encoded in `aio_data`
+    slots: Box<[Request<M>]>,
+    stats: StatsCounters,
+    closing: AtomicBool,
+    completed: Notify,
+    completion_mode: CompletionMode,
+    submitted: Notify,
+    health: Mutex<M, PollerHealth>,
     stop_tx: Mutex<M, Option<oneshot::Sender<()>>>,
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    GenericAioContextInner<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> GenericAioContextInner<M> {
     fn new(
-        eventfd: RawFd,
-        nr: usize,
-        use_semaphore: bool,
+        options: &AioContextOptions,
         stop_tx: oneshot::Sender<()>,
-    ) -> Result<GenericAioContextInner<M, A, L>, AioContextError> {
-        let mut context: aio::aio_context_t = 0;
-
-        unsafe {
-            if aio::io_setup(nr as libc::c_long, &mut context) != 0 {
-                return Err(AioContextError::IoSetup(io::Error::last_os_error()));
-            }
-        };
+    ) -> Result<GenericAioContextInner<M>, AioContextError> {
+        let eventfd = eventfd::new_fd(0, false)?;
+        let (backend, nr, fallback) = Backend::new(options, eventfd.as_fd())?;
 
         Ok(GenericAioContextInner {
-            context,
-            requests: Mutex::new(Requests::new(nr)?),
-            capacity: if use_semaphore {
+            backend,
+            fallback,
+            slots: requests::new_slab(nr),
+            requests: Mutex::new(Requests::new(nr)),
+            stats: StatsCounters::default(),
+            closing: AtomicBool::new(false),
+            completed: Notify::new(),
+            completion_mode: options.completion_mode,
+            submitted: Notify::new(),
+            health: Mutex::new(PollerHealth::Running),
+            capacity: if options.use_semaphore {
                 Some(Arc::new(Semaphore::new(nr)))
             } else {
                 None
@@ -99,65 +126,414 @@ where
             num_slots: nr,
         })
     }
+
+    /// Deliver the result of the request, which is completed by the kernel.
+    /// Completions of unknown slots, or of the previous submissions of the slot, are ignored
+    fn complete(&self, aio_data: u64, res: AioResult) {
+        let slot = requests::slot_of(aio_data);
+        let Some(request) = self
+            .slots
+            .get(slot)
+            .filter(|request| request.take_pending(aio_data))
+        else {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(aio_data, res, "stale AIO completion ignored");
+            return;
+        };
+
+        request_event!(trace, request.inner.lock(), res, "AIO request completed");
+        request.record_completion(&self.stats, res);
+
+        if !request.completion.complete(res) {
+            let mut requests = self.requests.lock();
+
+            // the slot can't be taken again, while the pool is locked
+            if requests.complete_unclaimed(slot) {
+                self.release_request(request);
+            }
+        }
+    }
+
+    /// Release the buffers and the permit of the request, which is returned to the pool
+    /// without its future. The pool must be locked
+    fn release_request(&self, request: &Request<M>) {
+        request.inner.lock().clear_buf_lifetime_extenders();
+
+        if let Some(c) = &self.capacity {
+            c.add_permits(1)
+        }
+    }
+
+    /// Deliver the results of the batch of completions, reaped from the kernel
+    fn dispatch(&self, events: &[aio::io_event]) {
+        for event in events {
+            self.complete(event.data, event.res);
+        }
+
+        self.completed.notify_waiters();
+    }
+
+    /// Fetch from `min_nr` to `events.capacity()` completions from the backend,
+    /// waiting up to `timeout`, and deliver them. Return the number of completions
+    fn get_events(
+        &self,
+        min_nr: usize,
+        timeout: Option<Duration>,
+        events: &mut Vec<aio::io_event>,
+    ) -> io::Result<usize> {
+        let num_received = match self.backend.get_events(min_nr, timeout, events) {
+            Ok(num_received) => num_received,
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                tracing::error!(error = %err, "fetching completions failed");
+                return Err(err);
+            }
+        };
+
+        if num_received > 0 {
+            self.dispatch(events);
+        }
+
+        Ok(num_received)
+    }
+
+    /// Requests, submitted to the kernel and not completed yet
+    fn pending_requests(&self) -> impl Iterator<Item = &Request<M>> {
+        self.slots
+            .iter()
+            .filter(|request| request.inner.lock().pending)
+    }
+
+    /// Number of pending requests, including the ones, which are being submitted
+    fn num_pending(&self) -> usize {
+        self.requests.lock().num_submitting() + self.pending_requests().count()
+    }
+
+    /// Wait until all pending requests are completed, or until `deadline`.
+    /// Return the number of requests, which are still pending
+    async fn wait_for_pending(&self, deadline: Option<Instant>) -> usize {
+        loop {
+            let notified = self.completed.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let num_pending = self.num_pending();
+            if num_pending == 0 {
+                return 0;
+            }
+
+            match deadline {
+                Some(deadline) => {
+                    if time::timeout_at(deadline, notified).await.is_err() {
+                        return self.num_pending();
+                    }
+                }
+                None => notified.await,
+            }
+        }
+    }
+
+    /// Reject new requests, wait for the pending ones and stop the poller.
+    /// If `timeout` passes, the rest of requests are cancelled
+    async fn drain(&self, timeout: Option<Duration>) -> CloseReport {
+        // the completed requests, which are not taken by their futures yet, are counted too.
+        // The pool is locked, so no more slots are taken after closing
+        let num_in_flight = {
+            let requests = self.requests.lock();
+            self.closing.store(true, Ordering::SeqCst);
+            self.num_slots - requests.num_ready()
+        };
+        let mut report = CloseReport::default();
+
+        let mut num_left = self
+            .wait_for_pending(timeout.map(|timeout| Instant::now() + timeout))
+            .await;
+
+        if let (Some(timeout), true) = (timeout, num_left > 0) {
+            let mut cancelled = vec![];
+
+            for request in self.pending_requests() {
+                match io_cancel_nowait(self, request) {
+                    Ok(Some(res)) => {
+                        self.complete(request.aio_data(), res);
+                        cancelled.push(request);
+                    }
+                    Ok(None) => cancelled.push(request),
+                    Err(_) => {}
+                }
+            }
+
+            num_left = self.wait_for_pending(Some(Instant::now() + timeout)).await;
+            // the kernel may complete the request regularly, before the cancellation
+            report.cancelled = cancelled
+                .into_iter()
+                .filter(|request| request.completed_as_cancelled())
+                .count();
+        }
+
+        report.abandoned = num_left;
+        report.completed = num_in_flight.saturating_sub(report.cancelled + report.abandoned);
+
+        if let Some(stop_tx) = self.stop_tx.lock().take() {
+            let _ = stop_tx.send(());
+        }
+
+        report
+    }
+
+    /// Record the exit of the poller, and fail the requests, which will never
+    /// receive the completion
+    fn poller_exited(&self, res: &Result<(), io::Error>) {
+        self.closing.store(true, Ordering::SeqCst);
+
+        *self.health.lock() = match res {
+            Ok(()) => PollerHealth::Stopped,
+            Err(e) => {
+                #[cfg(feature = "tracing")]
+                tracing::error!(error = %e, "AIO poller failed");
+                PollerHealth::Failed(Arc::new(match e.raw_os_error() {
+                    Some(code) => io::Error::from_raw_os_error(code),
+                    None => io::Error::new(e.kind(), e.to_string()),
+                }))
+            }
+        };
+
+        // the waiters are woken up with the error
+        for request in self.pending_requests() {
+            request.inner.lock().pending = false;
+            request.completion.abort();
+        }
+        // as well as the close, which waits for them
+        self.completed.notify_waiters();
+    }
+
+    /// Error for the requests, which can't be completed, because the poller is stopped
+    pub(crate) fn stopped_error(&self) -> AioCommandError {
+        match &*self.health.lock() {
+            PollerHealth::Failed(e) => AioCommandError::PollerFailed(e.clone()),
+            PollerHealth::Running | PollerHealth::Stopped => AioCommandError::AioStopped,
+        }
+    }
+
+    /// Wait for `num` permits, if the semaphore is used
+    async fn acquire_permits(&self, num: usize) -> Result<(), AioCommandError> {
+        if let Some(cap) = &self.capacity {
+            cap.acquire_many(num as u32)
+                .await
+                .map_err(|_| self.stopped_error())?
+                .forget();
+        }
+
+        Ok(())
+    }
+
+    /// Take the slots for the submission, once the permits are acquired. The permits
+    /// are released on failure
+    fn take_slots(&self, slots: &mut [usize]) -> Result<(), AioCommandError> {
+        let mut pool = self.requests.lock();
+
+        // the context may be closed while waiting for the permits
+        if self.closing.load(Ordering::SeqCst) {
+            if let Some(c) = &self.capacity {
+                c.add_permits(slots.len())
+            }
+
+            return Err(self.stopped_error());
+        }
+
+        for num_taken in 0..slots.len() {
+            match pool.take() {
+                Some(slot) => slots[num_taken] = slot,
+                None => {
+                    for &slot in &slots[..num_taken] {
+                        pool.return_in_flight_to_ready(slot);
+                    }
+                    if let Some(c) = &self.capacity {
+                        c.add_permits(slots.len())
+                    }
+
+                    return Err(AioCommandError::CapacityExceeded);
+                }
+            }
+        }
+
+        pool.start_submission(slots.len());
+
+        Ok(())
+    }
+
+    /// Submit the prepared requests. If the kernel accepts only the first part of them,
+    /// the remainder is resubmitted. The errors of rejected ones are stored in `submit_errors`
+    fn submit_requests(
+        &self,
+        request_ptrs: &mut [*mut aio::iocb],
+        submit_errors: &mut [Option<io::Error>],
+    ) {
+        let num_requests = request_ptrs.len();
+        let mut pos = 0;
+
+        while pos < num_requests {
+            match unsafe { self.backend.submit(&mut request_ptrs[pos..]) } {
+                Ok(num_accepted) if num_accepted > 0 => pos += num_accepted,
+                result => {
+                    // the first request in the remainder was rejected by the kernel
+                    submit_errors[pos] = Some(
+                        result
+                            .err()
+                            .unwrap_or_else(|| io::Error::from_raw_os_error(libc::EAGAIN)),
+                    );
+                    pos += 1;
+                }
+            }
+        }
+
+        if let CompletionMode::Polled { .. } = self.completion_mode {
+            self.submitted.notify_one();
+        }
+
+        self.requests.lock().finish_submission(num_requests);
+        // the requests may be already completed, while the close was waiting for them
+        self.completed.notify_waiters();
+    }
+
+    /// Future of the submitted request, or the failed one if it's rejected by the kernel
+    fn request_future<'a>(
+        self: &Arc<Self>,
+        slot: usize,
+        submit_error: Option<io::Error>,
+    ) -> AioRequestFuture<'a, M> {
+        let request = &self.slots[slot];
+        {
+            let request_inner = request.inner.lock();
+
+            #[cfg(feature = "tracing")]
+            match &submit_error {
+                None => request_event!(trace, request_inner, "AIO request submitted"),
+                Some(e) => request_event!(
+                    debug,
+                    request_inner,
+                    error = %e,
+                    "AIO request rejected by io_submit"
+                ),
+            }
+
+            self.stats
+                .record_submitted(request_inner.aio_req.aio_lio_opcode, submit_error.is_none());
+        }
+
+        match submit_error {
+            None => AioRequestFuture::new(AioWaitFuture::new(self, slot)),
+            Some(e) => {
+                {
+                    let mut request_inner = request.inner.lock();
+                    request_inner.pending = false;
+                    request_inner.clear_buf_lifetime_extenders();
+                }
+                self.requests.lock().return_in_flight_to_ready(slot);
+                if let Some(c) = &self.capacity {
+                    c.add_permits(1)
+                }
+                self.completed.notify_waiters();
+
+                AioRequestFuture::failed(AioCommandError::IoSubmit(e))
+            }
+        }
+    }
+
+    fn stats(&self) -> AioStats {
+        let (num_ready, num_outstanding) = {
+            let requests = self.requests.lock();
+            (requests.num_ready(), requests.num_outstanding())
+        };
+
+        AioStats {
+            num_slots: self.num_slots,
+            in_flight: self.num_slots - num_ready,
+            outstanding: num_outstanding,
+            available_slots: self.capacity.as_ref().map(|c| c.available_permits()),
+            opcodes: self.stats.snapshot(),
+        }
+    }
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    Drop for GenericAioContextInner<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> Drop for GenericAioContextInner<M> {
     fn drop(&mut self) {
-        let result = unsafe { aio::io_destroy(self.context) };
-        assert_eq!(0, result, "io_destroy returned bad code");
+        // like io_destroy, wait for the requests in flight, which may still use the buffers
+        match &self.backend {
+            Backend::KernelAio(_) => {}
+            Backend::IoUring(ring) => ring.cancel_all(
+                self.slots
+                    .iter()
+                    .map(|request| request.aio_iocb_ptr() as *const aio::iocb),
+            ),
+            // the blocking system calls can't be interrupted, so the pool keeps
+            // their buffers instead of waiting
+            Backend::ThreadPool(pool) => pool.cancel_all(self.slots.iter_mut().map(|request| {
+                let inner = request.inner.get_mut();
+                (
+                    mem::take(&mut inner.buf_lifetime_extenders),
+                    mem::take(&mut inner.iovecs),
+                )
+            })),
+        }
     }
 }
 
+/// Outcome of [`close_with_timeout`], by the requests which were in flight
+///
+/// [`close_with_timeout`]: struct.GenericAioContext.html#method.close_with_timeout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CloseReport {
+    /// Requests, completed by the kernel in time
+    pub completed: usize,
+    /// Requests, cancelled with `io_cancel`
+    pub cancelled: usize,
+    /// Requests, still in flight when the context was closed
+    pub abandoned: usize,
+}
+
+/// State of the background task, which polls the completions
+#[derive(Debug, Clone)]
+pub enum PollerHealth {
+    /// The task is running, or is not started yet
+    Running,
+    /// The task is stopped, because the context is closed or dropped
+    Stopped,
+    /// The task is stopped by the error
+    Failed(Arc<io::Error>),
+}
+
 /// Represents running AIO context. Must be kept while AIO is in use.
 /// In order to close it, [`close`] should be called. It will wait
 /// until all related futures are finished.
-/// Otherwise, if it just dropped, the termination will be triggered,
-/// but some running futures will continue running until they receive
-/// the data.
+/// Otherwise, if it just dropped, new requests are rejected, and the background
+/// task stops once the in-flight requests are completed.
+///
+/// If the background task fails, the waiting futures resolve to the `PollerFailed` error,
+/// and the failure is reported by [`health`].
 ///
 /// [`close`]: struct.GenericAioContext.html#method.close
-pub struct GenericAioContext<
-    M: RawMutex,
-    A: crate::IntrusiveAdapter<M, L>,
-    L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-> where
-    A::LinkOps: LinkedListOps + Default,
-{
-    inner: Arc<GenericAioContextInner<M, A, L>>,
+/// [`health`]: struct.GenericAioContext.html#method.health
+pub struct GenericAioContext<M: AioLock> {
+    inner: Arc<GenericAioContextInner<M>>,
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    fmt::Debug for GenericAioContext<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> fmt::Debug for GenericAioContext<M> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AioContext")
             .field("num_slots", &self.inner.num_slots)
+            .field("backend", &self.inner.backend.kind())
+            .field("fallback", &self.inner.fallback)
             .finish()
     }
 }
 
 /// Cloneable handle to AIO context. Required for any AIO operations
-pub struct GenericAioContextHandle<
-    M: RawMutex,
-    A: crate::IntrusiveAdapter<M, L>,
-    L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-> where
-    A::LinkOps: LinkedListOps + Default,
-{
-    inner: Weak<GenericAioContextInner<M, A, L>>,
+pub struct GenericAioContextHandle<M: AioLock> {
+    inner: Weak<GenericAioContextInner<M>>,
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    Clone for GenericAioContextHandle<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> Clone for GenericAioContextHandle<M> {
     fn clone(&self) -> Self {
         GenericAioContextHandle {
             inner: self.inner.clone(),
@@ -165,11 +541,7 @@ where
     }
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    GenericAioContextHandle<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> GenericAioContextHandle<M> {
     /// Number of available AIO slots left in the context
     ///
     /// Return None if AIO context stopped, or if `use_semaphore`
@@ -180,6 +552,29 @@ where
             .and_then(|i| i.capacity.as_ref().map(|c| c.available_permits()))
     }
 
+    /// State of the background task. `Stopped` if the context is dropped
+    pub fn health(&self) -> PollerHealth {
+        self.inner
+            .upgrade()
+            .map_or(PollerHealth::Stopped, |i| i.health.lock().clone())
+    }
+
+    /// Snapshot of the context statistics. Return None if AIO context stopped
+    pub fn stats(&self) -> Option<AioStats> {
+        self.inner.upgrade().map(|i| i.stats())
+    }
+
+    /// Backend, selected on the context creation. Return None if AIO context stopped
+    pub fn backend(&self) -> Option<AioBackend> {
+        self.inner.upgrade().map(|i| i.backend.kind())
+    }
+
+    /// Total number of AIO slots in the context. None if AIO context stopped
+    #[cfg(feature = "tokio")]
+    pub(crate) fn num_slots(&self) -> Option<usize> {
+        self.inner.upgrade().map(|i| i.num_slots)
+    }
+
     /// Submit command to the AIO context
     ///
     /// If `use_semaphore` set to `false`, this function will return
@@ -188,75 +583,179 @@ where
     pub async fn submit_request(
         &self,
         fd: &impl AsRawFd,
-        mut command: RawCommand<'_>,
+        command: RawCommand<'_>,
     ) -> Result<u64, AioCommandError> {
-        let inner_context = self
-            .inner
-            .upgrade()
-            .ok_or(AioCommandError::AioStopped)?
-            .clone();
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!(
+            "aio_request",
+            fd = fd.as_raw_fd(),
+            opcode = ?AioOpcode::from_raw(command.opcode() as u16),
+        );
 
-        if let Some(cap) = &inner_context.capacity {
-            cap.acquire().await.expect("semaphore closed").forget();
-        }
+        let request = async move { self.submit_single(fd.as_raw_fd(), command).await?.await };
 
-        let mut request = inner_context
-            .requests
-            .lock()
-            .take()
-            .ok_or(AioCommandError::CapacityExceeded)?;
+        #[cfg(feature = "tracing")]
+        let request = tracing::Instrument::instrument(request, span);
 
-        let request_addr = request.aio_addr();
+        request.await
+    }
 
-        let (tx, rx) = oneshot::channel();
+    /// Wait until the file descriptor becomes ready for any of the requested `events`
+    /// with `IOCB_CMD_POLL`. Requires Linux 4.18+
+    ///
+    /// Resolves to the returned events. `ERR` and `HUP` are always reported,
+    /// even if not requested.
+    pub async fn poll_fd(
+        &self,
+        fd: &impl AsRawFd,
+        events: PollFlags,
+    ) -> Result<PollFlags, AioCommandError> {
+        let revents = self.submit_request(fd, RawCommand::Poll { events }).await?;
 
-        let result = {
-            let mut request_ptr_array: [*mut aio::iocb; 1] = [ptr::null_mut(); 1];
+        Ok(PollFlags::from_bits_truncate(revents as libc::c_short))
+    }
 
-            request.set_payload(
-                &mut request_ptr_array,
-                request_addr,
-                inner_context.eventfd,
-                fd.as_raw_fd(),
-                &mut command,
-                tx,
-            );
+    /// Submit command to the AIO context, giving up at `deadline`
+    ///
+    /// The deadline covers both waiting for a free slot and waiting for the completion.
+    /// When it passes, the request is cancelled with `io_cancel`, and the `TimedOut` error
+    /// reports whether the cancellation succeeded. If the request completes while being
+    /// cancelled, its regular result is returned.
+    #[cfg(feature = "tokio")]
+    pub async fn submit_request_with_deadline(
+        &self,
+        fd: &impl AsRawFd,
+        command: RawCommand<'_>,
+        deadline: Instant,
+    ) -> Result<u64, AioCommandError> {
+        let is_poll = matches!(command, RawCommand::Poll { .. });
 
-            unsafe { aio::io_submit(inner_context.context, 1, request_ptr_array.as_mut_ptr()) }
-        };
+        let mut future =
+            match time::timeout_at(deadline, self.submit_single(fd.as_raw_fd(), command)).await {
+                Ok(future) => future?,
+                Err(_) => return Err(AioCommandError::TimedOut { cancelled: true }),
+            };
+
+        if let Ok(res) = time::timeout_at(deadline, &mut future).await {
+            return res;
+        }
+
+        if future.cancel().is_err() {
+            return Err(AioCommandError::TimedOut { cancelled: false });
+        }
 
-        if result != 1 {
-            mem::drop(request.inner.lock().take_buf_lifetime_extender());
-            inner_context
-                .requests
-                .lock()
-                .return_in_flight_to_ready(request);
-            if let Some(c) = &inner_context.capacity {
-                c.add_permits(1)
+        // cancelled polls complete with no events, while other commands may
+        // legitimately complete with zero, e.g. fsync, or read at the end of file
+        match future.await {
+            Ok(0) if is_poll => Err(AioCommandError::TimedOut { cancelled: true }),
+            Err(AioCommandError::BadResult(e)) if e.raw_os_error() == Some(libc::ECANCELED) => {
+                Err(AioCommandError::TimedOut { cancelled: true })
             }
+            res => res,
+        }
+    }
+
+    /// Submit a batch of commands to the AIO context with a single `io_submit` call
+    ///
+    /// Returns one future per command, in the same order. All the slots are reserved
+    /// at once, so the batch should not be larger than the number of slots in the context,
+    /// otherwise `CapacityExceeded` error is returned.
+    ///
+    /// If the kernel accepts only the first part of the batch, the remainder is
+    /// resubmitted. A command rejected by `io_submit` resolves to the `IoSubmit` error,
+    /// while the rest of the batch proceeds.
+    pub async fn submit_batch<'a, F: AsRawFd>(
+        &self,
+        commands: impl IntoIterator<Item = (F, RawCommand<'a>)>,
+    ) -> Result<Vec<AioRequestFuture<'a, M>>, AioCommandError> {
+        let inner_context = self.running_context()?;
+
+        let commands: Vec<_> = commands
+            .into_iter()
+            .map(|(fd, command)| (fd.as_raw_fd(), command))
+            .collect();
+        let num_commands = commands.len();
+
+        if num_commands == 0 {
+            return Ok(vec![]);
+        }
 
-            return Err(AioCommandError::IoSubmit(io::Error::last_os_error()));
+        if num_commands > inner_context.num_slots {
+            return Err(AioCommandError::CapacityExceeded);
         }
 
-        let base = AioWaitFuture::new(&inner_context, rx, request);
+        for (_, command) in &commands {
+            command.check_len()?;
+        }
+
+        inner_context.acquire_permits(num_commands).await?;
+
+        let mut slots = vec![0; num_commands];
+        inner_context.take_slots(&mut slots)?;
+
+        let mut request_ptrs: Vec<_> = slots
+            .iter()
+            .zip(commands)
+            .map(|(&slot, (fd, mut command))| {
+                inner_context.slots[slot].set_payload(
+                    inner_context.eventfd.as_raw_fd(),
+                    fd,
+                    &mut command,
+                )
+            })
+            .collect();
+
+        let mut submit_errors: Vec<Option<io::Error>> = (0..num_commands).map(|_| None).collect();
+        inner_context.submit_requests(&mut request_ptrs, &mut submit_errors);
+
+        Ok(slots
+            .into_iter()
+            .zip(submit_errors)
+            .map(|(slot, submit_error)| inner_context.request_future(slot, submit_error))
+            .collect())
+    }
+
+    /// Submit a single command, like `submit_batch` does, without allocations
+    async fn submit_single<'a>(
+        &self,
+        fd: RawFd,
+        mut command: RawCommand<'a>,
+    ) -> Result<AioRequestFuture<'a, M>, AioCommandError> {
+        let inner_context = self.running_context()?;
+
+        command.check_len()?;
 
-        let code = base.await?;
+        inner_context.acquire_permits(1).await?;
 
-        if code < 0 {
-            Err(AioCommandError::BadResult(io::Error::from_raw_os_error(
-                -code as _,
-            )))
-        } else {
-            Ok(code.try_into().unwrap())
+        let mut slot = [0];
+        inner_context.take_slots(&mut slot)?;
+
+        let mut request_ptrs = [inner_context.slots[slot[0]].set_payload(
+            inner_context.eventfd.as_raw_fd(),
+            fd,
+            &mut command,
+        )];
+
+        let mut submit_errors = [None];
+        inner_context.submit_requests(&mut request_ptrs, &mut submit_errors);
+
+        let [submit_error] = submit_errors;
+        Ok(inner_context.request_future(slot[0], submit_error))
+    }
+
+    /// Context, which accepts new requests
+    fn running_context(&self) -> Result<Arc<GenericAioContextInner<M>>, AioCommandError> {
+        let inner_context = self.inner.upgrade().ok_or(AioCommandError::AioStopped)?;
+
+        if inner_context.closing.load(Ordering::SeqCst) {
+            return Err(inner_context.stopped_error());
         }
+
+        Ok(inner_context)
     }
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    fmt::Debug for GenericAioContextHandle<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> fmt::Debug for GenericAioContextHandle<M> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AioContextHandle").finish()
     }
@@ -264,40 +763,77 @@ where
 
 /// Create new AIO context with `nr` number of threads
 ///
+/// The returned future polls the completions, and must be spawned on tokio runtime.
+/// To use the context without tokio, see
+/// [`generic_aio_context_with_reaper`](fn.generic_aio_context_with_reaper.html)
+///
 /// If `use_semaphore` is set to true, the request sending future
 /// will wait until the kernel thread is freed. Otherwise, no wait
 /// for available capacity occurs. It's the user's code
 /// responsibility to ensure that number of in-flight queries
 /// doesn't exceed the number of kernel threads.
+#[cfg(feature = "tokio")]
 #[allow(clippy::type_complexity)]
-pub fn generic_aio_context<M, A, L>(
+pub fn generic_aio_context<M>(
     nr: usize,
     use_semaphore: bool,
 ) -> Result<
     (
-        GenericAioContext<M, A, L>,
-        GenericAioContextHandle<M, A, L>,
+        GenericAioContext<M>,
+        GenericAioContextHandle<M>,
         impl Future<Output = Result<(), io::Error>>,
     ),
     AioContextError,
 >
 where
-    A: crate::IntrusiveAdapter<M, L>,
-    A::LinkOps: LinkedListOps + Default,
-    L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-    M: RawMutex,
+    M: AioLock,
+{
+    generic_aio_context_with_options(AioContextOptions::new(nr).use_semaphore(use_semaphore))
+}
+
+/// Create new AIO context with the `options`
+///
+/// See [`generic_aio_context`](fn.generic_aio_context.html) for more details
+#[cfg(feature = "tokio")]
+#[allow(clippy::type_complexity)]
+pub fn generic_aio_context_with_options<M>(
+    options: AioContextOptions,
+) -> Result<
+    (
+        GenericAioContext<M>,
+        GenericAioContextHandle<M>,
+        impl Future<Output = Result<(), io::Error>>,
+    ),
+    AioContextError,
+>
+where
+    M: AioLock,
 {
-    let mut eventfd = EventFd::new(0, false)?;
     let (stop_tx, stop_rx) = oneshot::channel();
 
-    let inner = Arc::new(GenericAioContextInner::new(
-        eventfd.as_raw_fd(),
-        nr,
-        use_semaphore,
-        stop_tx,
-    )?);
+    let inner = Arc::new(GenericAioContextInner::new(&options, stop_tx)?);
+    let nr = inner.num_slots;
+
+    let mut eventfd = EventFd::from_fd(
+        inner
+            .eventfd
+            .try_clone()
+            .map_err(eventfd::EventFdError::CreateError)?,
+    )?;
+
+    let mut busy_poll = match (options.completion_mode, &inner.backend) {
+        (CompletionMode::BusyPoll { idle_polls }, Backend::KernelAio(context)) => {
+            unsafe { AioRing::new(*context) }.map(|ring| (Some(ring), idle_polls))
+        }
+        // the completions of other backends are always fetched without system calls
+        (CompletionMode::BusyPoll { idle_polls }, _) => Some((None, idle_polls)),
+        _ => None,
+    };
 
-    let context = inner.context;
+    let polled_spin = match options.completion_mode {
+        CompletionMode::Polled { spin } => Some(spin),
+        _ => None,
+    };
 
     let poll_future = {
         let inner = inner.clone();
@@ -305,54 +841,124 @@ where
         async move {
             let mut events = Vec::with_capacity(nr);
 
-            while let Ok(available) = eventfd.recv().await {
-                assert!(available > 0, "kernel reported zero ready events");
-                assert!(
-                    available <= nr as u64,
-                    "kernel reported more events than number of maximum tasks"
-                );
+            loop {
+                let available = match polled_spin {
+                    Some(_) => tokio::select! {
+                        available = eventfd.recv() => available,
+                        // spinning starts right after the submission
+                        _ = inner.submitted.notified() => Ok(0),
+                    },
+                    None => eventfd.recv().await,
+                };
+
+                let Ok(available) = available else {
+                    break;
+                };
+
+                if let Some(spin) = polled_spin {
+                    // eventfd counter only wakes up the task, completions are fetched
+                    // without blocking until none arrive for `spin`
+                    let mut idle_since = Instant::now();
+
+                    while idle_since.elapsed() < spin {
+                        if inner.get_events(0, Some(Duration::ZERO), &mut events)? > 0 {
+                            idle_since = Instant::now();
+                        }
+
+                        tokio::task::yield_now().await;
+                    }
+
+                    continue;
+                }
+
+                if available == 0 {
+                    return Err(poller_error("kernel reported zero ready events"));
+                }
+
+                if let Some((ring, idle_polls)) = &mut busy_poll {
+                    // eventfd counter only wakes up the task, the ring is the source of events
+                    let mut empty_polls = 0;
+
+                    while empty_polls <= *idle_polls {
+                        let num_reaped = match ring {
+                            Some(ring) => {
+                                events.clear();
+                                let num_reaped = ring.reap(&mut events);
+                                if num_reaped > 0 {
+                                    inner.dispatch(&events);
+                                }
+                                num_reaped
+                            }
+                            None => inner.get_events(0, Some(Duration::ZERO), &mut events)?,
+                        };
+
+                        if num_reaped == 0 {
+                            empty_polls += 1;
+                            tokio::task::yield_now().await;
+                            continue;
+                        }
+
+                        empty_polls = 0;
+
+                        #[cfg(feature = "tracing")]
+                        tracing::trace!(num_events = num_reaped, "reaped AIO ring");
+                    }
+
+                    continue;
+                }
+
+                let context = match &inner.backend {
+                    Backend::KernelAio(context) => *context,
+                    // io_uring may signal eventfd once for several completions, and
+                    // the thread pool may signal after the completion is fetched,
+                    // so all available ones are fetched
+                    _ => {
+                        while inner.get_events(0, Some(Duration::ZERO), &mut events)?
+                            == events.capacity()
+                        {}
+
+                        continue;
+                    }
+                };
+
+                if available > nr as u64 {
+                    return Err(poller_error(
+                        "kernel reported more events than number of maximum tasks",
+                    ));
+                }
+
+                #[cfg(feature = "tracing")]
+                let _span = tracing::trace_span!("aio_getevents", available).entered();
 
                 unsafe {
+                    // the events are in the ring before eventfd is signalled, so no wait is needed
                     let num_received = aio::io_getevents(
                         context,
                         available as libc::c_long,
                         available as libc::c_long,
                         events.as_mut_ptr(),
-                        ptr::null_mut::<aio::timespec>(),
+                        &mut aio::timespec {
+                            tv_sec: 0,
+                            tv_nsec: 0,
+                        },
                     );
 
                     if num_received < 0 {
-                        return Err(io::Error::last_os_error());
+                        let err = io::Error::last_os_error();
+                        #[cfg(feature = "tracing")]
+                        tracing::error!(error = %err, "io_getevents failed, stopping the poller");
+                        return Err(err);
                     }
 
-                    assert!(
-                        num_received == available as _,
-                        "io_getevents received events num not equal to reported through eventfd"
-                    );
-                    events.set_len(available as usize);
+                    events.set_len(num_received as usize);
                 };
 
-                for event in &events {
-                    let request_ptr = event.data as usize as *mut Request<M, L>;
-
-                    let sent_succeeded = unsafe { &*request_ptr }.send_to_waiter(event.res);
-
-                    if !sent_succeeded {
-                        mem::drop(
-                            unsafe { &*request_ptr }
-                                .inner
-                                .lock()
-                                .take_buf_lifetime_extender(),
-                        );
-                        inner
-                            .requests
-                            .lock()
-                            .return_outstanding_to_ready(request_ptr);
-
-                        if let Some(c) = &inner.capacity {
-                            c.add_permits(1)
-                        }
-                    }
+                inner.dispatch(&events);
+
+                if events.len() != available as usize {
+                    return Err(poller_error(
+                        "io_getevents received events num not equal to reported through eventfd",
+                    ));
                 }
             }
 
@@ -360,13 +966,31 @@ where
         }
     };
 
-    let background = async move {
-        tokio::pin!(poll_future);
-        tokio::pin!(stop_rx);
+    let background = {
+        let inner = inner.clone();
+
+        async move {
+            let exit = PollerExit(Some(inner.clone()));
+            tokio::pin!(poll_future);
+
+            let res = tokio::select! {
+                res = &mut poll_future => res,
+                stop = stop_rx => match stop {
+                    Ok(()) => Ok(()),
+                    // the context is dropped without close, so the in-flight requests
+                    // are served until completed
+                    Err(_) => {
+                        inner.closing.store(true, Ordering::SeqCst);
 
-        tokio::select! {
-            res = &mut poll_future => res,
-            _ = &mut stop_rx => Ok(()),
+                        tokio::select! {
+                            res = &mut poll_future => res,
+                            _ = inner.wait_for_pending(None) => Ok(()),
+                        }
+                    }
+                },
+            };
+
+            exit.finish(res)
         }
     };
 
@@ -377,22 +1001,133 @@ where
     Ok((GenericAioContext { inner }, handle, background))
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    GenericAioContext<M, A, L>
+/// Records the exit of the poller, even if the background future is dropped
+/// before completion, e.g. when its task is aborted or the runtime is shut down
+#[cfg(feature = "tokio")]
+struct PollerExit<M: AioLock>(Option<Arc<GenericAioContextInner<M>>>);
+
+#[cfg(feature = "tokio")]
+impl<M: AioLock> PollerExit<M> {
+    fn finish(mut self, res: Result<(), io::Error>) -> Result<(), io::Error> {
+        if let Some(inner) = self.0.take() {
+            inner.poller_exited(&res);
+        }
+
+        res
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<M: AioLock> Drop for PollerExit<M> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.0.take() {
+            inner.poller_exited(&Ok(()));
+        }
+    }
+}
+
+/// Inconsistency between eventfd and the completions, which stops the poller
+#[cfg(feature = "tokio")]
+fn poller_error(message: &'static str) -> io::Error {
+    #[cfg(feature = "tracing")]
+    tracing::error!(message, "AIO poller failed");
+
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Create new AIO context with the `options`, which doesn't depend on any async runtime
+///
+/// Instead of the background task, the completions are delivered by the returned
+/// [`GenericAioReaper`]: either when its eventfd becomes readable, or by a thread,
+/// blocked in `io_getevents`. The completion mode of the `options` is not used.
+///
+/// [`GenericAioReaper`]: struct.GenericAioReaper.html
+#[allow(clippy::type_complexity)]
+pub fn generic_aio_context_with_reaper<M>(
+    options: AioContextOptions,
+) -> Result<
+    (
+        GenericAioContext<M>,
+        GenericAioContextHandle<M>,
+        GenericAioReaper<M>,
+    ),
+    AioContextError,
+>
 where
-    A::LinkOps: LinkedListOps + Default,
+    M: AioLock,
 {
+    // nobody waits for the stop signal, the reaper is stopped by drop
+    let (stop_tx, _) = oneshot::channel();
+
+    let inner = Arc::new(GenericAioContextInner::new(&options, stop_tx)?);
+
+    let handle = GenericAioContextHandle {
+        inner: Arc::downgrade(&inner),
+    };
+    let reaper = GenericAioReaper::new(inner.clone());
+
+    Ok((GenericAioContext { inner }, handle, reaper))
+}
+
+impl<M: AioLock> GenericAioContext<M> {
     /// Number of available AIO slots left in the context
     pub fn available_slots(&self) -> Option<usize> {
         self.inner.capacity.as_ref().map(|c| c.available_permits())
     }
 
+    /// Total number of AIO slots in the context. Less than requested,
+    /// if the context is shrunk to the system limit
+    pub fn num_slots(&self) -> usize {
+        self.inner.num_slots
+    }
+
+    /// State of the background task
+    pub fn health(&self) -> PollerHealth {
+        self.inner.health.lock().clone()
+    }
+
+    /// Snapshot of the context statistics: request counters and latencies
+    /// by opcode, and the usage of slots
+    pub fn stats(&self) -> AioStats {
+        self.inner.stats()
+    }
+
+    /// Backend, which executes the requests. May differ from the requested one,
+    /// if `io_uring` is not available
+    pub fn backend(&self) -> AioBackend {
+        self.inner.backend.kind()
+    }
+
+    /// Why the backend differs from the requested one. None if the requested backend
+    /// is used. If neither `io_uring`, nor kernel AIO is available, the reason of
+    /// the fallback to `ThreadPool` is reported
+    pub fn fallback(&self) -> Option<&AioFallback> {
+        self.inner.fallback.as_ref()
+    }
+
     /// Close the AIO context and wait for all related running futures to complete.
     pub async fn close(self) {
-        self.inner.stop_tx.lock().take().unwrap().send(()).unwrap();
-        while Arc::strong_count(&self.inner) != 1 {
-            task::yield_now().await;
-        }
+        self.inner.drain(None).await;
+    }
+
+    /// Close the AIO context, waiting up to `timeout` for the in-flight requests
+    ///
+    /// New requests are rejected with `AioStopped` error. Requests, which are not
+    /// completed within `timeout`, are cancelled with `io_cancel`, and the cancellations
+    /// are awaited for up to `timeout` again. Requests, which are still in flight after
+    /// that, are abandoned: their futures resolve to `AioStopped` error.
+    #[cfg(feature = "tokio")]
+    pub async fn close_with_timeout(self, timeout: Duration) -> CloseReport {
+        self.inner.drain(Some(timeout)).await
+    }
+}
+
+impl<M: AioLock> Drop for GenericAioContext<M> {
+    fn drop(&mut self) {
+        self.inner.closing.store(true, Ordering::SeqCst);
+
+        // dropped sender lets the poller stop, once the in-flight requests are completed
+        mem::drop(self.inner.stop_tx.lock().take());
     }
 }
 
@@ -401,6 +1136,7 @@ where
 /// eventfd with `tokio::spawn`.
 ///
 /// See [`generic_aio_context`](fn.generic_aio_context.html) for more details
+#[cfg(feature = "tokio")]
 #[inline]
 pub fn aio_context(
     nr: usize,
@@ -412,18 +1148,45 @@ pub fn aio_context(
     Ok((aio_context, aio_handle))
 }
 
+/// Create new AIO context with the `options`, suitable for cross-threaded environment.
+/// Automatically spawn background task with `tokio::spawn`.
+///
+/// See [`aio_context`](fn.aio_context.html) for more details
+#[cfg(feature = "tokio")]
+#[inline]
+pub fn aio_context_with_options(
+    options: AioContextOptions,
+) -> Result<(AioContext, AioContextHandle), AioContextError> {
+    let (aio_context, aio_handle, background) = generic_aio_context_with_options(options)?;
+    tokio::spawn(background);
+
+    Ok((aio_context, aio_handle))
+}
+
+/// Create new AIO context with the `options`, suitable for cross-threaded environment,
+/// and the reaper of its completions, which doesn't depend on any async runtime
+///
+/// See [`generic_aio_context_with_reaper`](fn.generic_aio_context_with_reaper.html)
+/// for more details
+#[inline]
+pub fn aio_context_with_reaper(
+    options: AioContextOptions,
+) -> Result<(AioContext, AioContextHandle, AioReaper), AioContextError> {
+    generic_aio_context_with_reaper(options)
+}
+
 /// AIO context suitable for cross-threaded environment (tokio rt-threaded),
 /// backed by parking_lot Mutex
-pub type AioContext = GenericAioContext<parking_lot::RawMutex, SyncRequestAdapter, AtomicLink>;
+pub type AioContext = GenericAioContext<parking_lot::RawMutex>;
 
 /// AIO context handle suitable for cross-threaded environment (tokio rt-threaded),
 /// backed by parking_lot Mutex
-pub type AioContextHandle =
-    GenericAioContextHandle<parking_lot::RawMutex, SyncRequestAdapter, AtomicLink>;
+pub type AioContextHandle = GenericAioContextHandle<parking_lot::RawMutex>;
 
 /// Create new AIO context suitable for single-threaded environment (tokio rt-core)
 ///
 /// See [`generic_aio_context`](fn.generic_aio_context.html) for more details.
+#[cfg(feature = "tokio")]
 #[inline]
 pub fn local_aio_context(
     nr: usize,
@@ -440,8 +1203,52 @@ pub fn local_aio_context(
 }
 
 /// AIO context suitable for cross-threaded environment (tokio rt-core)
-pub type LocalAioContext = GenericAioContext<NoopLock, LocalRequestAdapter, linked_list::Link>;
+pub type LocalAioContext = GenericAioContext<NoopLock>;
 
 /// AIO context handle suitable for single-threaded environment (tokio rt-core)
-pub type LocalAioContextHandle =
-    GenericAioContextHandle<NoopLock, LocalRequestAdapter, linked_list::Link>;
+pub type LocalAioContextHandle = GenericAioContextHandle<NoopLock>;
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn poller_failure_aborts_waiters() {
+        let (aio, aio_handle, background) =
+            generic_aio_context_with_options::<parking_lot::RawMutex>(
+                AioContextOptions::new(1).backend(AioBackend::KernelAio),
+            )
+            .unwrap();
+        let background = tokio::spawn(background);
+
+        let mut fds = [0 as RawFd; 2];
+        assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+        let (read_end, _write_end) =
+            unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+
+        let poll = tokio::spawn({
+            let aio_handle = aio_handle.clone();
+            async move { aio_handle.poll_fd(&read_end, PollFlags::IN).await }
+        });
+        while aio.available_slots() != Some(0) {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // more completions are signalled than the context has slots
+        let count = 2u64.to_ne_bytes();
+        assert_eq!(8, unsafe {
+            libc::write(
+                aio.inner.eventfd.as_raw_fd(),
+                count.as_ptr() as *const libc::c_void,
+                count.len(),
+            )
+        });
+
+        assert!(background.await.unwrap().is_err());
+        assert!(matches!(
+            poll.await.unwrap(),
+            Err(AioCommandError::PollerFailed(_))
+        ));
+        assert!(matches!(aio_handle.health(), PollerHealth::Failed(_)));
+    }
+}
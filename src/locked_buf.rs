@@ -3,11 +3,13 @@
 use std::cell::UnsafeCell;
 use std::mem::ManuallyDrop;
 use std::sync::Arc;
-use std::{fmt, io};
+use std::{fmt, io, slice};
 
 use memmap2::MmapMut;
 use thiserror::Error;
 
+use crate::locked_buf_pool::LockedBufPoolInner;
+
 /// Error during [`LockedBuf`] creation
 ///
 /// [`LockedBuf`]: struct.LockedBuf.html
@@ -20,11 +22,71 @@ pub enum LockedBufError {
     /// Error in `mlock` invocation
     #[error("mlock error: `{0}`")]
     MemLock(#[from] region::Error),
+
+    /// Sub-slice doesn't fit into the buffer
+    #[error("range {offset}..{end} is out of bounds of the buffer with size {size}")]
+    OutOfBounds {
+        /// Start of the requested range
+        offset: usize,
+        /// End of the requested range
+        end: usize,
+        /// Size of the buffer
+        size: usize,
+    },
+
+    /// Sub-slice offset is not aligned for `O_DIRECT` I/O
+    #[error("offset {offset} is not aligned to {alignment} bytes")]
+    Misaligned {
+        /// Requested offset
+        offset: usize,
+        /// Required alignment
+        alignment: usize,
+    },
+}
+
+/// Minimal alignment of [`LockedBufSlice`] offsets, which is the smallest logical
+/// block size, supported for `O_DIRECT` I/O
+///
+/// [`LockedBufSlice`]: struct.LockedBufSlice.html
+pub const LOCKED_BUF_SLICE_ALIGNMENT: usize = 512;
+
+pub(crate) mod private {
+    use crate::locked_buf::LifetimeExtender;
+
+    pub trait Sealed {
+        fn aio_addr_and_len(&self) -> (u64, u64);
+
+        fn lifetime_extender(&self) -> LifetimeExtender;
+    }
 }
 
-struct LockedBufInner {
-    bytes: ManuallyDrop<MmapMut>,
-    mlock_guard: ManuallyDrop<region::LockGuard>,
+/// Memory locked to RAM, which may be used as the buffer of AIO commands:
+/// [`LockedBuf`] or [`LockedBufSlice`]
+///
+/// [`LockedBuf`]: struct.LockedBuf.html
+/// [`LockedBufSlice`]: struct.LockedBufSlice.html
+pub trait AioBuffer:
+    AsRef<[u8]> + AsMut<[u8]> + fmt::Debug + Send + Sync + private::Sealed
+{
+}
+
+enum Backing {
+    /// Memory, mapped and locked for this buffer only
+    Mapped {
+        bytes: ManuallyDrop<MmapMut>,
+        mlock_guard: ManuallyDrop<region::LockGuard>,
+    },
+    /// Memory, borrowed from the pool
+    Pooled {
+        pool: Arc<LockedBufPoolInner>,
+        index: usize,
+    },
+}
+
+pub(crate) struct LockedBufInner {
+    ptr: *mut u8,
+    len: usize,
+    backing: Backing,
 }
 
 /// Buffer with fixed capacity, locked to RAM. It prevents
@@ -43,7 +105,7 @@ impl fmt::Debug for LockedBuf {
     }
 }
 
-pub(crate) struct LifetimeExtender {
+pub struct LifetimeExtender {
     _inner: Arc<UnsafeCell<LockedBufInner>>,
 }
 
@@ -56,26 +118,53 @@ impl fmt::Debug for LifetimeExtender {
 impl LockedBuf {
     /// Create with desired capacity
     pub fn with_size(size: usize) -> Result<LockedBuf, LockedBufError> {
-        let bytes = MmapMut::map_anon(size)?;
+        let mut bytes = MmapMut::map_anon(size)?;
         let mlock_guard = region::lock(bytes.as_ref().as_ptr(), size)?;
 
         Ok(LockedBuf {
             inner: Arc::new(UnsafeCell::new(LockedBufInner {
-                bytes: ManuallyDrop::new(bytes),
-                mlock_guard: ManuallyDrop::new(mlock_guard),
+                ptr: bytes.as_mut_ptr(),
+                len: size,
+                backing: Backing::Mapped {
+                    bytes: ManuallyDrop::new(bytes),
+                    mlock_guard: ManuallyDrop::new(mlock_guard),
+                },
             })),
         })
     }
 
+    /// Wrap the buffer of the pool. It's returned to the pool on drop
+    pub(crate) fn pooled(pool: Arc<LockedBufPoolInner>, index: usize) -> LockedBuf {
+        let (ptr, len) = pool.buffer_ptr_and_len(index);
+
+        LockedBuf {
+            inner: Arc::new(UnsafeCell::new(LockedBufInner {
+                ptr,
+                len,
+                backing: Backing::Pooled { pool, index },
+            })),
+        }
+    }
+
     /// Return current capacity
     pub fn size(&self) -> usize {
-        unsafe { &*self.inner.get() }.bytes.len()
+        unsafe { &*self.inner.get() }.len
+    }
+
+    /// Convert into the slice, covering the whole buffer
+    pub fn into_slice(self) -> LockedBufSlice {
+        let len = self.size();
+
+        LockedBufSlice {
+            inner: self.inner,
+            offset: 0,
+            len,
+        }
     }
 
     pub(crate) fn aio_addr_and_len(&self) -> (u64, u64) {
-        let len = unsafe { &*self.inner.get() }.bytes.len() as u64;
-        let ptr = unsafe { (*self.inner.get()).bytes.as_ptr() as usize } as u64;
-        (ptr, len)
+        let inner = unsafe { &*self.inner.get() };
+        (inner.ptr as usize as u64, inner.len as u64)
     }
 
     /// Handle, which prevents LockedBuf to drop while request is in-flight
@@ -86,25 +175,40 @@ impl LockedBuf {
     }
 }
 
+impl private::Sealed for LockedBuf {
+    fn aio_addr_and_len(&self) -> (u64, u64) {
+        LockedBuf::aio_addr_and_len(self)
+    }
+
+    fn lifetime_extender(&self) -> LifetimeExtender {
+        LockedBuf::lifetime_extender(self)
+    }
+}
+
+impl AioBuffer for LockedBuf {}
+
 impl AsRef<[u8]> for LockedBuf {
     fn as_ref(&self) -> &[u8] {
         let inner = unsafe { &*self.inner.get() };
-        inner.bytes.as_ref()
+        unsafe { slice::from_raw_parts(inner.ptr, inner.len) }
     }
 }
 
 impl AsMut<[u8]> for LockedBuf {
     fn as_mut(&mut self) -> &mut [u8] {
         let inner = unsafe { &mut *self.inner.get() };
-        inner.bytes.as_mut()
+        unsafe { slice::from_raw_parts_mut(inner.ptr, inner.len) }
     }
 }
 
 impl Drop for LockedBufInner {
     fn drop(&mut self) {
-        unsafe {
-            ManuallyDrop::drop(&mut self.mlock_guard);
-            ManuallyDrop::drop(&mut self.bytes);
+        match &mut self.backing {
+            Backing::Mapped { bytes, mlock_guard } => unsafe {
+                ManuallyDrop::drop(mlock_guard);
+                ManuallyDrop::drop(bytes);
+            },
+            Backing::Pooled { pool, index } => pool.release(*index),
         }
     }
 }
@@ -112,4 +216,143 @@ impl Drop for LockedBufInner {
 unsafe impl Send for LockedBuf {}
 unsafe impl Sync for LockedBuf {}
 
+/// Region of [`LockedBuf`], which may be used for I/O independently from other regions
+///
+/// The slices are created by splitting the buffer, so they never overlap. The memory
+/// of the parent buffer is kept alive while any of its slices exists, and while the
+/// slice is used by an in-flight request.
+///
+/// [`LockedBuf`]: struct.LockedBuf.html
+pub struct LockedBufSlice {
+    inner: Arc<UnsafeCell<LockedBufInner>>,
+    offset: usize,
+    len: usize,
+}
+
+impl fmt::Debug for LockedBufSlice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LockedBufSlice")
+            .field("offset", &self.offset)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl LockedBufSlice {
+    /// Offset of the slice in the parent buffer
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Length of the slice
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return true if the slice is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Split the slice into two at `mid`. The offset of the second slice in the parent
+    /// buffer must be aligned to [`LOCKED_BUF_SLICE_ALIGNMENT`].
+    ///
+    /// [`LOCKED_BUF_SLICE_ALIGNMENT`]: constant.LOCKED_BUF_SLICE_ALIGNMENT.html
+    pub fn split_at(self, mid: usize) -> Result<(LockedBufSlice, LockedBufSlice), LockedBufError> {
+        if mid > self.len {
+            return Err(LockedBufError::OutOfBounds {
+                offset: self.offset,
+                end: self.offset + mid,
+                size: self.offset + self.len,
+            });
+        }
+
+        let second_offset = self.offset + mid;
+        if !second_offset.is_multiple_of(LOCKED_BUF_SLICE_ALIGNMENT) {
+            return Err(LockedBufError::Misaligned {
+                offset: second_offset,
+                alignment: LOCKED_BUF_SLICE_ALIGNMENT,
+            });
+        }
+
+        let second = LockedBufSlice {
+            inner: self.inner.clone(),
+            offset: second_offset,
+            len: self.len - mid,
+        };
+
+        let first = LockedBufSlice {
+            inner: self.inner,
+            offset: self.offset,
+            len: mid,
+        };
+
+        Ok((first, second))
+    }
+
+    /// Split the slice into chunks of `chunk_size`. The last chunk may be shorter.
+    /// `chunk_size` must be aligned to [`LOCKED_BUF_SLICE_ALIGNMENT`].
+    ///
+    /// [`LOCKED_BUF_SLICE_ALIGNMENT`]: constant.LOCKED_BUF_SLICE_ALIGNMENT.html
+    pub fn chunks(self, chunk_size: usize) -> Result<Vec<LockedBufSlice>, LockedBufError> {
+        if chunk_size == 0 || !chunk_size.is_multiple_of(LOCKED_BUF_SLICE_ALIGNMENT) {
+            return Err(LockedBufError::Misaligned {
+                offset: self.offset + chunk_size,
+                alignment: LOCKED_BUF_SLICE_ALIGNMENT,
+            });
+        }
+
+        let mut chunks = Vec::with_capacity(self.len.div_ceil(chunk_size));
+        let mut rest = self;
+
+        while rest.len > chunk_size {
+            let (chunk, tail) = rest.split_at(chunk_size)?;
+            chunks.push(chunk);
+            rest = tail;
+        }
+        chunks.push(rest);
+
+        Ok(chunks)
+    }
+
+    fn as_ptr(&self) -> *mut u8 {
+        unsafe { (*self.inner.get()).ptr.add(self.offset) }
+    }
+}
+
+impl From<LockedBuf> for LockedBufSlice {
+    fn from(buf: LockedBuf) -> Self {
+        buf.into_slice()
+    }
+}
+
+impl AsRef<[u8]> for LockedBufSlice {
+    fn as_ref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+}
+
+impl AsMut<[u8]> for LockedBufSlice {
+    fn as_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.as_ptr(), self.len) }
+    }
+}
+
+impl private::Sealed for LockedBufSlice {
+    fn aio_addr_and_len(&self) -> (u64, u64) {
+        (self.as_ptr() as usize as u64, self.len as u64)
+    }
+
+    fn lifetime_extender(&self) -> LifetimeExtender {
+        LifetimeExtender {
+            _inner: self.inner.clone(),
+        }
+    }
+}
+
+impl AioBuffer for LockedBufSlice {}
+
+unsafe impl Send for LockedBufSlice {}
+unsafe impl Sync for LockedBufSlice {}
+
 unsafe impl Send for LifetimeExtender {}
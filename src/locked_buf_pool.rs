@@ -0,0 +1,154 @@
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+
+use memmap2::MmapMut;
+use parking_lot::Mutex;
+use tokio::sync::Semaphore;
+
+use crate::{LockedBuf, LockedBufError};
+
+pub(crate) struct LockedBufPoolInner {
+    base: *mut u8,
+    buf_size: usize,
+    num_bufs: usize,
+    free: Mutex<Vec<usize>>,
+    available: Semaphore,
+    bytes: ManuallyDrop<MmapMut>,
+    mlock_guard: ManuallyDrop<region::LockGuard>,
+}
+
+impl LockedBufPoolInner {
+    pub(crate) fn buffer_ptr_and_len(&self, index: usize) -> (*mut u8, usize) {
+        assert!(index < self.num_bufs);
+        (
+            unsafe { self.base.add(index * self.buf_size) },
+            self.buf_size,
+        )
+    }
+
+    pub(crate) fn release(&self, index: usize) {
+        self.free.lock().push(index);
+        self.available.add_permits(1);
+    }
+}
+
+impl Drop for LockedBufPoolInner {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.mlock_guard);
+            ManuallyDrop::drop(&mut self.bytes);
+        }
+    }
+}
+
+unsafe impl Send for LockedBufPoolInner {}
+unsafe impl Sync for LockedBufPoolInner {}
+
+/// Snapshot of [`LockedBufPool`] usage
+///
+/// [`LockedBufPool`]: struct.LockedBufPool.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockedBufPoolStats {
+    /// Number of buffers, handed out from the pool, including the ones
+    /// still used by in-flight requests
+    pub in_use: usize,
+    /// Number of buffers, ready to be handed out
+    pub free: usize,
+}
+
+/// Pool of fixed-size [`LockedBuf`]s, carved from the single region,
+/// which is mapped and locked to RAM once
+///
+/// Buffers are returned to the pool when dropped. If the buffer is used by the
+/// in-flight request, it's returned once the request is completed by the kernel.
+/// The content of the buffer is not cleared between uses.
+///
+/// [`LockedBuf`]: struct.LockedBuf.html
+#[derive(Clone)]
+pub struct LockedBufPool {
+    inner: Arc<LockedBufPoolInner>,
+}
+
+impl fmt::Debug for LockedBufPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LockedBufPool")
+            .field("buf_size", &self.inner.buf_size)
+            .field("num_bufs", &self.inner.num_bufs)
+            .finish()
+    }
+}
+
+impl LockedBufPool {
+    /// Create the pool of `num_bufs` buffers of `buf_size` bytes. The size is
+    /// rounded up to the page size, so every buffer is suitably aligned for `O_DIRECT`
+    pub fn new(buf_size: usize, num_bufs: usize) -> Result<LockedBufPool, LockedBufError> {
+        let page_size = region::page::size();
+        let buf_size = buf_size.div_ceil(page_size).max(1) * page_size;
+        let size = buf_size * num_bufs;
+
+        let mut bytes = MmapMut::map_anon(size)?;
+        let mlock_guard = region::lock(bytes.as_ref().as_ptr(), size)?;
+
+        Ok(LockedBufPool {
+            inner: Arc::new(LockedBufPoolInner {
+                base: bytes.as_mut_ptr(),
+                buf_size,
+                num_bufs,
+                free: Mutex::new((0..num_bufs).rev().collect()),
+                available: Semaphore::new(num_bufs),
+                bytes: ManuallyDrop::new(bytes),
+                mlock_guard: ManuallyDrop::new(mlock_guard),
+            }),
+        })
+    }
+
+    /// Size of every buffer in the pool
+    pub fn buf_size(&self) -> usize {
+        self.inner.buf_size
+    }
+
+    /// Total number of buffers in the pool
+    pub fn capacity(&self) -> usize {
+        self.inner.num_bufs
+    }
+
+    /// Take the free buffer from the pool. Return `None` if all buffers are in use
+    pub fn try_get(&self) -> Option<LockedBuf> {
+        self.inner.available.try_acquire().ok()?.forget();
+        Some(self.take_free())
+    }
+
+    /// Take the free buffer from the pool, waiting until one is returned
+    /// if all buffers are in use
+    pub async fn get(&self) -> LockedBuf {
+        self.inner
+            .available
+            .acquire()
+            .await
+            .expect("semaphore closed")
+            .forget();
+        self.take_free()
+    }
+
+    /// Current usage of the pool
+    pub fn stats(&self) -> LockedBufPoolStats {
+        let free = self.inner.free.lock().len();
+
+        LockedBufPoolStats {
+            in_use: self.inner.num_bufs - free,
+            free,
+        }
+    }
+
+    fn take_free(&self) -> LockedBuf {
+        let index = self
+            .inner
+            .free
+            .lock()
+            .pop()
+            .expect("no free buffer while semaphore permit acquired");
+
+        LockedBuf::pooled(self.inner.clone(), index)
+    }
+}
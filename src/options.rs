@@ -0,0 +1,146 @@
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How the background task receives the completions from the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CompletionMode {
+    /// Wait for the eventfd notification and fetch the completions with `io_getevents`
+    #[default]
+    EventFd,
+    /// Read the completions from the ring, which the kernel maps to user space,
+    /// without system calls
+    ///
+    /// After every batch the ring is checked again, yielding to other tasks in between,
+    /// until it stays empty `idle_polls` times in a row. Then the task falls back to
+    /// waiting for the eventfd notification. If the kernel ring layout is not
+    /// recognized, `EventFd` mode is used. With `io_uring`, its completion queue is read.
+    BusyPoll {
+        /// Number of empty ring checks before waiting for eventfd
+        idle_polls: u32,
+    },
+    /// Fetch the completions with non-blocking `io_getevents`, starting right after
+    /// the submission, meant for `HIPRI` requests
+    ///
+    /// The task keeps polling, yielding to other tasks in between, until no completions
+    /// arrive for `spin`. Then it falls back to waiting for the eventfd notification
+    /// or the next submission.
+    ///
+    /// Only the task polls: the devices still signal the completions with interrupts.
+    /// Kernel AIO drops `HIPRI`, and polled completion by the device is available only
+    /// to `io_uring` rings, set up for polling. The `IoUring` backend doesn't set them up,
+    /// and strips the flag, which the other rings reject.
+    Polled {
+        /// How long to poll without completions before waiting
+        spin: Duration,
+    },
+}
+
+/// Kernel interface, which executes the requests of the context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AioBackend {
+    /// Kernel AIO: `io_submit`, and `io_getevents` for the completions
+    #[default]
+    KernelAio,
+    /// `io_uring`, which requires Linux 5.11+
+    ///
+    /// If the kernel doesn't support it, or it's disabled or blocked by seccomp,
+    /// the context falls back to `KernelAio`. The commands, which the kernel rejects,
+    /// e.g. with a bad file descriptor, fail with `BadResult` instead of `IoSubmit` error.
+    /// The commands of 4 GiB and more fail with `IoSubmit` error `EINVAL`.
+    /// `HIPRI` flag is ignored. Explicit cancellation waits for its outcome, while
+    /// the dropped requests are cancelled in the background.
+    IoUring,
+    /// Blocking system calls on the blocking threads of tokio runtime
+    ///
+    /// If the context is created outside of tokio runtime, the calls are executed by
+    /// the dedicated threads: no more than `nr`, and 4 per CPU core. The waiting polls
+    /// hold the threads, so the other requests may be delayed by them.
+    ///
+    /// Used when `io_setup` fails with `ENOSYS` or `EAGAIN`, or the system-wide limit
+    /// `fs.aio-max-nr` doesn't allow the context. Only polls can be cancelled, the other
+    /// requests, running when the context is dropped, keep their buffers until they finish.
+    /// Like with `IoUring`, the commands, which the kernel rejects, fail with `BadResult` error.
+    ThreadPool,
+}
+
+/// Why the context doesn't use the requested backend. See [`fallback`]
+///
+/// [`fallback`]: struct.GenericAioContext.html#method.fallback
+#[derive(Debug, Clone)]
+pub enum AioFallback {
+    /// `io_uring` is not available, so `KernelAio` is used
+    IoUringUnavailable(Arc<io::Error>),
+    /// The system-wide limit `fs.aio-max-nr` doesn't allow the context, so `ThreadPool`
+    /// is used
+    SystemLimit {
+        /// Requested number of slots
+        requested: usize,
+        /// Number of slots left under the limit
+        available: usize,
+    },
+    /// `io_setup` failed with `ENOSYS` or `EAGAIN`, so `ThreadPool` is used
+    IoSetup(Arc<io::Error>),
+}
+
+/// Parameters of the AIO context. See [`generic_aio_context_with_options`]
+///
+/// [`generic_aio_context_with_options`]: fn.generic_aio_context_with_options.html
+#[derive(Debug, Clone)]
+pub struct AioContextOptions {
+    pub(crate) nr: usize,
+    pub(crate) use_semaphore: bool,
+    pub(crate) completion_mode: CompletionMode,
+    pub(crate) backend: AioBackend,
+    pub(crate) min_nr: Option<usize>,
+    pub(crate) fail_on_system_limit: bool,
+}
+
+impl AioContextOptions {
+    /// Options of the context with `nr` slots, which waits for the available
+    /// slots and receives completions through eventfd
+    pub fn new(nr: usize) -> Self {
+        AioContextOptions {
+            nr,
+            use_semaphore: true,
+            completion_mode: CompletionMode::default(),
+            backend: AioBackend::default(),
+            min_nr: None,
+            fail_on_system_limit: false,
+        }
+    }
+
+    /// Whether the request sending future waits for the available slot.
+    /// See [`generic_aio_context`](fn.generic_aio_context.html)
+    pub fn use_semaphore(mut self, use_semaphore: bool) -> Self {
+        self.use_semaphore = use_semaphore;
+        self
+    }
+
+    /// How the completions are received
+    pub fn completion_mode(mut self, completion_mode: CompletionMode) -> Self {
+        self.completion_mode = completion_mode;
+        self
+    }
+
+    /// Which kernel interface executes the requests
+    pub fn backend(mut self, backend: AioBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// If `fs.aio-max-nr` system limit doesn't allow `nr` slots, create the context
+    /// with the available ones, but not less than `min_nr`. The actual number is
+    /// reported by [`num_slots`](struct.GenericAioContext.html#method.num_slots)
+    pub fn shrink_to(mut self, min_nr: usize) -> Self {
+        self.min_nr = Some(min_nr);
+        self
+    }
+
+    /// Whether to fail with `SystemLimit` error, if `fs.aio-max-nr` system limit
+    /// doesn't allow the context. Otherwise, the requests are executed by `ThreadPool`
+    pub fn fail_on_system_limit(mut self, fail_on_system_limit: bool) -> Self {
+        self.fail_on_system_limit = fail_on_system_limit;
+        self
+    }
+}
@@ -0,0 +1,121 @@
+use std::future::Future;
+use std::os::unix::prelude::*;
+use std::pin::pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::Duration;
+use std::{fmt, io};
+
+use crate::{AioLock, GenericAioContextInner, NoopLock, eventfd};
+
+/// How long `block_on` waits for the completions, before polling the future again
+const BLOCK_ON_WAIT: Duration = Duration::from_millis(10);
+
+/// Delivers the completions of the AIO context without any async runtime.
+/// See [`generic_aio_context_with_reaper`]
+///
+/// With an event loop, [`eventfd`] should be watched for readability, and [`reap`]
+/// called when it's readable. Without one, the thread may block in [`reap_blocking`],
+/// or drive the request futures with [`block_on`]. Once the reaper is dropped,
+/// the requests in flight fail with `AioStopped` error.
+///
+/// [`generic_aio_context_with_reaper`]: fn.generic_aio_context_with_reaper.html
+/// [`eventfd`]: struct.GenericAioReaper.html#method.eventfd
+/// [`reap`]: struct.GenericAioReaper.html#method.reap
+/// [`reap_blocking`]: struct.GenericAioReaper.html#method.reap_blocking
+/// [`block_on`]: struct.GenericAioReaper.html#method.block_on
+pub struct GenericAioReaper<M: AioLock> {
+    inner: Arc<GenericAioContextInner<M>>,
+}
+
+impl<M: AioLock> fmt::Debug for GenericAioReaper<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AioReaper")
+            .field("eventfd", &self.inner.eventfd)
+            .finish()
+    }
+}
+
+impl<M: AioLock> GenericAioReaper<M> {
+    pub(crate) fn new(inner: Arc<GenericAioContextInner<M>>) -> Self {
+        GenericAioReaper { inner }
+    }
+
+    /// Non-blocking eventfd, which becomes readable when the requests complete
+    pub fn eventfd(&self) -> BorrowedFd<'_> {
+        self.inner.eventfd.as_fd()
+    }
+
+    /// Deliver the available completions without blocking, and reset the readiness
+    /// of eventfd. Return the number of delivered completions
+    pub fn reap(&self) -> io::Result<usize> {
+        // the counter is reset before fetching, so later completions signal eventfd again
+        eventfd::try_read(self.eventfd()).map_err(io::Error::other)?;
+
+        let mut events = Vec::with_capacity(self.inner.num_slots);
+        let mut num_reaped = 0;
+
+        loop {
+            let num_received = self
+                .inner
+                .get_events(0, Some(Duration::ZERO), &mut events)?;
+            num_reaped += num_received;
+
+            if num_received < events.capacity() {
+                return Ok(num_reaped);
+            }
+        }
+    }
+
+    /// Wait with `io_getevents` up to `timeout`, or without a limit if it's `None`,
+    /// until at least one request completes, and deliver the available completions.
+    /// Return the number of delivered completions, which is zero if the wait timed out
+    pub fn reap_blocking(&self, timeout: Option<Duration>) -> io::Result<usize> {
+        let mut events = Vec::with_capacity(self.inner.num_slots);
+
+        self.inner.get_events(1, timeout, &mut events)
+    }
+
+    /// Run `future` to completion on the current thread, delivering the completions
+    /// while it's pending
+    ///
+    /// Allows to use the request futures from blocking code.
+    pub fn block_on<F: Future>(&self, future: F) -> io::Result<F::Output> {
+        let woken = Arc::new(WokenFlag(AtomicBool::new(false)));
+        let waker = Waker::from(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut future = pin!(future);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return Ok(output);
+            }
+
+            // the future may be woken by another thread, e.g. when the slot is freed
+            if !woken.0.swap(false, Ordering::AcqRel) {
+                self.reap_blocking(Some(BLOCK_ON_WAIT))?;
+            }
+        }
+    }
+}
+
+impl<M: AioLock> Drop for GenericAioReaper<M> {
+    fn drop(&mut self) {
+        self.inner.poller_exited(&Ok(()));
+    }
+}
+
+struct WokenFlag(AtomicBool);
+
+impl Wake for WokenFlag {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// AIO reaper suitable for cross-threaded environment, backed by parking_lot Mutex
+pub type AioReaper = GenericAioReaper<parking_lot::RawMutex>;
+
+/// AIO reaper suitable for single-threaded environment
+pub type LocalAioReaper = GenericAioReaper<NoopLock>;
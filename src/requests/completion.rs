@@ -0,0 +1,182 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicI64, AtomicU8, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::{fmt, mem};
+
+use atomic_waker::AtomicWaker;
+use lock_api::RawMutex;
+
+use crate::{AioResult, NoopLock};
+
+/// Lock, which guards the requests of the context
+///
+/// It also selects how the results are handed to the request futures: through
+/// `AtomicWaker` for `parking_lot::RawMutex`, and through plain cells for `NoopLock`.
+/// Other thread-safe locks may reuse the completion of `parking_lot::RawMutex`.
+pub trait AioLock: RawMutex {
+    /// Result of the request and the waker of its future
+    type Completion: Completion;
+}
+
+impl AioLock for parking_lot::RawMutex {
+    type Completion = AtomicCompletion;
+}
+
+impl AioLock for NoopLock {
+    type Completion = LocalCompletion;
+}
+
+/// Nothing happened since the request was submitted
+const PENDING: u8 = 0;
+/// The result is stored
+const COMPLETED: u8 = 1;
+/// The future is dropped, and never takes the result
+const CLOSED: u8 = 2;
+/// The poller is stopped, and never delivers the result
+const ABORTED: u8 = 3;
+
+/// Slot, through which the poller hands the result to the future of the request
+pub trait Completion: Default + fmt::Debug {
+    /// Prepare for the new submission of the request
+    fn reset(&self);
+
+    /// Store the result, and wake the future. Return false if the future is already dropped
+    fn complete(&self, res: AioResult) -> bool;
+
+    /// Wake the future, which will never receive the result
+    fn abort(&self);
+
+    /// Mark the future dropped, and return the result if it's already stored
+    fn close(&self) -> Option<AioResult>;
+
+    /// Poll for the result. Resolves to `None` if it's aborted
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<Option<AioResult>>;
+}
+
+/// Completion, which may be delivered from another thread
+#[derive(Debug, Default)]
+pub struct AtomicCompletion {
+    state: AtomicU8,
+    res: AtomicI64,
+    waker: AtomicWaker,
+}
+
+impl Completion for AtomicCompletion {
+    fn reset(&self) {
+        mem::drop(self.waker.take());
+        self.state.store(PENDING, Ordering::Release);
+    }
+
+    fn complete(&self, res: AioResult) -> bool {
+        self.res.store(res, Ordering::Relaxed);
+
+        match self
+            .state
+            .compare_exchange(PENDING, COMPLETED, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => {
+                self.waker.wake();
+                true
+            }
+            Err(state) => state != CLOSED,
+        }
+    }
+
+    fn abort(&self) {
+        if self
+            .state
+            .compare_exchange(PENDING, ABORTED, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            self.waker.wake();
+        }
+    }
+
+    fn close(&self) -> Option<AioResult> {
+        match self.state.swap(CLOSED, Ordering::AcqRel) {
+            COMPLETED => Some(self.res.load(Ordering::Relaxed)),
+            _ => None,
+        }
+    }
+
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<Option<AioResult>> {
+        // registered before checking, so the completion in between is not missed
+        self.waker.register(cx.waker());
+
+        match self.state.load(Ordering::Acquire) {
+            COMPLETED => Poll::Ready(Some(self.res.load(Ordering::Relaxed))),
+            ABORTED => Poll::Ready(None),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+/// Completion, which is delivered on the same thread
+#[derive(Default)]
+pub struct LocalCompletion {
+    state: Cell<u8>,
+    res: Cell<AioResult>,
+    waker: Cell<Option<Waker>>,
+}
+
+impl fmt::Debug for LocalCompletion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LocalCompletion")
+            .field("state", &self.state)
+            .field("res", &self.res)
+            .finish()
+    }
+}
+
+impl Completion for LocalCompletion {
+    fn reset(&self) {
+        self.waker.set(None);
+        self.state.set(PENDING);
+    }
+
+    fn complete(&self, res: AioResult) -> bool {
+        match self.state.get() {
+            PENDING => {
+                self.res.set(res);
+                self.state.set(COMPLETED);
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+                true
+            }
+            state => state != CLOSED,
+        }
+    }
+
+    fn abort(&self) {
+        if self.state.get() == PENDING {
+            self.state.set(ABORTED);
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn close(&self) -> Option<AioResult> {
+        match self.state.replace(CLOSED) {
+            COMPLETED => Some(self.res.get()),
+            _ => None,
+        }
+    }
+
+    fn poll(&self, cx: &mut Context<'_>) -> Poll<Option<AioResult>> {
+        match self.state.get() {
+            COMPLETED => Poll::Ready(Some(self.res.get())),
+            ABORTED => Poll::Ready(None),
+            _ => {
+                let waker = match self.waker.take() {
+                    Some(waker) if waker.will_wake(cx.waker()) => waker,
+                    _ => cx.waker().clone(),
+                };
+                self.waker.set(Some(waker));
+
+                Poll::Pending
+            }
+        }
+    }
+}
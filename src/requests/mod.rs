@@ -1,156 +1,331 @@
 #![allow(clippy::unneeded_field_pattern)]
 
-use std::marker::PhantomData;
+use std::mem;
 use std::os::unix::prelude::*;
-use std::{io, mem};
+use std::time::Instant;
 
-use intrusive_collections::linked_list::LinkedListOps;
-use intrusive_collections::{DefaultLinkOps, LinkedList};
-use lock_api::{Mutex, RawMutex};
-use tokio::sync::oneshot;
+use lock_api::Mutex;
 
 use crate::locked_buf::LifetimeExtender;
-pub use crate::requests::atomic_link::AtomicLink;
+use crate::stats::StatsCounters;
 use crate::{AioResult, RawCommand, aio};
 
-pub use self::intrusive_adapter::{IntrusiveAdapter, LocalRequestAdapter, SyncRequestAdapter};
+pub use self::completion::{AioLock, Completion};
 
-mod atomic_link;
-mod intrusive_adapter;
+mod completion;
+
+/// Bits of `aio_data`, which hold the slot index. The rest hold the generation
+const SLOT_BITS: u32 = 32;
+
+/// The generation wraps before reaching the two highest bits of `aio_data`, which stay
+/// free for the backends
+const GENERATION_MASK: u32 = u32::MAX >> 2;
+
+/// Slot index, encoded in `aio_data`
+pub(crate) fn slot_of(aio_data: u64) -> usize {
+    (aio_data & ((1 << SLOT_BITS) - 1)) as usize
+}
+
+/// Whether the result reports the cancellation: `ECANCELED`, or no events for polls,
+/// which otherwise always report some
+fn is_cancelled(raw_opcode: u16, res: AioResult) -> bool {
+    res == -libc::ECANCELED as AioResult || (raw_opcode as u32 == aio::IOCB_CMD_POLL && res == 0)
+}
+
+/// Same layout as `struct iovec`, but with the address stored as integer, like in `aio_buf`
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct IoVec {
+    iov_base: usize,
+    iov_len: usize,
+}
 
 #[derive(Debug)]
 pub(crate) struct RequestInner {
     pub aio_req: aio::iocb,
-    pub completed_tx: Option<oneshot::Sender<AioResult>>,
-    pub buf_lifetime_extender: Option<LifetimeExtender>,
+    pub iovecs: Vec<IoVec>,
+    /// Submitted, and the completion is not delivered yet
+    pub pending: bool,
+    pub buf_lifetime_extenders: Vec<LifetimeExtender>,
+    pub submitted_at: Option<Instant>,
+    /// Result of the last completion
+    pub res: AioResult,
+    /// Incremented on every submission, so the completions of the previous
+    /// submissions of the slot are recognized
+    pub generation: u32,
 }
 
 impl RequestInner {
-    pub(crate) fn take_buf_lifetime_extender(&mut self) -> Option<LifetimeExtender> {
-        self.buf_lifetime_extender.take()
+    /// Release the buffers, keeping the capacity for the next submission of the slot
+    pub(crate) fn clear_buf_lifetime_extenders(&mut self) {
+        self.buf_lifetime_extenders.clear();
     }
 }
 
 #[derive(Debug)]
-pub struct Request<M: RawMutex, L: DefaultLinkOps + Default> {
-    link: L,
+pub(crate) struct Request<M: AioLock> {
+    slot: usize,
     pub(crate) inner: Mutex<M, RequestInner>,
+    pub(crate) completion: M::Completion,
 }
 
-impl<M: RawMutex, L: DefaultLinkOps + Default> Default for Request<M, L> {
-    fn default() -> Self {
+impl<M: AioLock> Request<M> {
+    fn new(slot: usize) -> Self {
         Request {
-            link: Default::default(),
+            slot,
             inner: Mutex::new(RequestInner {
                 aio_req: unsafe { mem::zeroed() },
-                completed_tx: None,
-                buf_lifetime_extender: None,
+                iovecs: Vec::new(),
+                pending: false,
+                buf_lifetime_extenders: Vec::new(),
+                submitted_at: None,
+                res: 0,
+                generation: 0,
             }),
+            completion: Default::default(),
         }
     }
-}
-impl<M: RawMutex, L: DefaultLinkOps + Default> Request<M, L> {
-    pub fn aio_addr(&self) -> u64 {
-        (self as *const Self as usize) as u64
+
+    /// Slot and generation of the last submission, as passed to the kernel
+    pub fn aio_data(&self) -> u64 {
+        self.inner.lock().aio_req.aio_data
     }
 
-    pub fn send_to_waiter(&self, data: AioResult) -> bool {
-        self.inner
-            .lock()
-            .completed_tx
-            .take()
-            .expect("no completed_tx in received AIO request")
-            .send(data)
-            .is_ok()
+    pub fn aio_iocb_ptr(&self) -> *mut aio::iocb {
+        &mut self.inner.lock().aio_req as *mut aio::iocb
+    }
+
+    /// Mark the request completed, if `aio_data` belongs to the current submission,
+    /// and it's not completed yet. Return false otherwise
+    pub fn take_pending(&self, aio_data: u64) -> bool {
+        let mut inner = self.inner.lock();
+
+        inner.aio_req.aio_data == aio_data && mem::take(&mut inner.pending)
+    }
+
+    pub fn record_completion(&self, stats: &StatsCounters, res: AioResult) {
+        let mut inner = self.inner.lock();
+        inner.res = res;
+
+        stats.record_completion(
+            inner.aio_req.aio_lio_opcode,
+            inner.submitted_at,
+            is_cancelled(inner.aio_req.aio_lio_opcode, res),
+            res,
+        );
+    }
+
+    /// Whether the last submission is completed as cancelled: with `ECANCELED`,
+    /// or with no events for polls
+    pub fn completed_as_cancelled(&self) -> bool {
+        let inner = self.inner.lock();
+
+        !inner.pending && is_cancelled(inner.aio_req.aio_lio_opcode, inner.res)
     }
 
     pub fn set_payload(
-        &mut self,
-        request_ptr_array: &mut [*mut aio::iocb; 1],
-        request_addr: u64,
+        &self,
         eventfd: RawFd,
         fd: RawFd,
         command: &mut RawCommand,
-        tx: oneshot::Sender<AioResult>,
-    ) {
+    ) -> *mut aio::iocb {
         let inner = &mut *self.inner.lock();
 
-        let (addr, buf_len) = command.buffer_addr().unwrap_or((0, 0));
-        let len = command.len().unwrap_or(0);
+        inner.iovecs.clear();
 
-        assert!(len <= buf_len, "len should be <= buffer.size()");
+        let (addr, len) = if let Some(buffers) = command.vectored_buffers() {
+            inner.iovecs.extend(buffers.map(|buffer| {
+                let (iov_base, iov_len) = buffer.aio_addr_and_len();
+                IoVec {
+                    iov_base: iov_base as usize,
+                    iov_len: iov_len as usize,
+                }
+            }));
 
-        inner.aio_req.aio_data = request_addr;
+            (
+                inner.iovecs.as_ptr() as usize as u64,
+                inner.iovecs.len() as u64,
+            )
+        } else if let Some(events) = command.poll_events() {
+            // poll events are passed in place of the buffer address
+            (events, 0)
+        } else {
+            let (addr, buf_len) = command.buffer_addr().unwrap_or((0, 0));
+            let len = command.len().unwrap_or(0);
+
+            // checked before the submission, the kernel must not write past the buffer
+            debug_assert!(len <= buf_len, "len should be <= buffer.size()");
+
+            (addr, len)
+        };
+
+        inner.generation = inner.generation.wrapping_add(1) & GENERATION_MASK;
+
+        inner.aio_req.aio_data = (inner.generation as u64) << SLOT_BITS | self.slot as u64;
         inner.aio_req.aio_resfd = eventfd as u32;
-        inner.aio_req.aio_flags = aio::IOCB_FLAG_RESFD | command.flags().unwrap_or(0);
+        inner.aio_req.aio_flags = aio::IOCB_FLAG_RESFD;
+        inner.aio_req.aio_rw_flags = command.flags().unwrap_or(0) as aio::__kernel_rwf_t;
         inner.aio_req.aio_fildes = fd as u32;
         inner.aio_req.aio_offset = command.offset().unwrap_or(0) as i64;
         inner.aio_req.aio_buf = addr;
         inner.aio_req.aio_nbytes = len;
         inner.aio_req.aio_lio_opcode = command.opcode() as u16;
 
-        inner.buf_lifetime_extender = command.buffer_lifetime_extender();
-        inner.completed_tx = Some(tx);
+        command.buffer_lifetime_extenders(&mut inner.buf_lifetime_extenders);
+        inner.pending = true;
+        self.completion.reset();
+        inner.submitted_at = Some(Instant::now());
 
-        request_ptr_array[0] = &mut inner.aio_req as *mut aio::iocb;
+        &mut inner.aio_req as *mut aio::iocb
     }
 }
 
-pub struct Requests<
-    M: RawMutex,
-    A: crate::IntrusiveAdapter<M, L>,
-    L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-> where
-    A::LinkOps: LinkedListOps + Default,
-{
-    ready_pool: LinkedList<A>,
-    outstanding: LinkedList<A>,
-    _request_mutex: PhantomData<M>,
-    _link_ops: PhantomData<L>,
+/// Fixed slab of requests, which never move in memory, so the kernel may refer to them
+pub(crate) fn new_slab<M: AioLock>(nr: usize) -> Box<[Request<M>]> {
+    (0..nr).map(Request::new).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SlotState {
+    Ready,
+    /// Owned by the future, which waits for the completion
+    InFlight,
+    /// Completed, while its future was being dropped
+    Completed,
+    /// Still in the kernel, while its future is dropped
+    Outstanding,
 }
 
-impl<M, A, L> Requests<M, A, L>
-where
-    M: RawMutex,
-    A: crate::IntrusiveAdapter<M, L>,
-    A::LinkOps: LinkedListOps + Default,
-    L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-{
-    pub fn new(nr: usize) -> Result<Self, io::Error> {
-        let outstanding = LinkedList::new(A::new());
-        let mut ready_pool = LinkedList::new(A::new());
-
-        for _ in 0..nr {
-            ready_pool.push_back(Box::new(Request::default()));
+/// Pool of the slab slots, by their state
+#[derive(Debug)]
+pub(crate) struct Requests {
+    ready: Vec<usize>,
+    states: Vec<SlotState>,
+    num_outstanding: usize,
+    /// Slots, which are taken for the submission, and not submitted yet
+    num_submitting: usize,
+}
+
+impl Requests {
+    pub fn new(nr: usize) -> Self {
+        Requests {
+            ready: (0..nr).rev().collect(),
+            states: vec![SlotState::Ready; nr],
+            num_outstanding: 0,
+            num_submitting: 0,
         }
+    }
 
-        Ok(Requests {
-            ready_pool,
-            outstanding,
-            _request_mutex: Default::default(),
-            _link_ops: Default::default(),
-        })
+    pub fn take(&mut self) -> Option<usize> {
+        let slot = self.ready.pop()?;
+        self.states[slot] = SlotState::InFlight;
+        Some(slot)
     }
 
-    pub fn move_to_outstanding(&mut self, ptr: Box<Request<M, L>>) {
-        self.outstanding.push_back(ptr);
+    pub fn return_in_flight_to_ready(&mut self, slot: usize) {
+        debug_assert_ne!(SlotState::Ready, self.states[slot]);
+
+        self.states[slot] = SlotState::Ready;
+        self.ready.push(slot);
     }
 
-    pub fn return_outstanding_to_ready(&mut self, request: *const Request<M, L>) {
-        let mut cursor = unsafe { self.outstanding.cursor_mut_from_ptr(request) };
+    /// Keep the slot, which is dropped by its future, until the kernel completes it.
+    /// Return false if it's already completed, then the slot is returned to ready
+    pub fn move_to_outstanding(&mut self, slot: usize) -> bool {
+        if self.states[slot] == SlotState::Completed {
+            self.return_in_flight_to_ready(slot);
+            return false;
+        }
 
-        self.ready_pool.push_back(
-            cursor.remove().expect(
-                "Could not find item in outstanding list while trying to move to ready_pool",
-            ),
-        );
+        self.states[slot] = SlotState::Outstanding;
+        self.num_outstanding += 1;
+        true
+    }
+
+    /// Reclaim the slot, which is completed without a waiter. Return true if the slot
+    /// is returned to ready, otherwise its future is being dropped, and reclaims it
+    pub fn complete_unclaimed(&mut self, slot: usize) -> bool {
+        match self.states[slot] {
+            SlotState::Outstanding => {
+                self.num_outstanding -= 1;
+                self.return_in_flight_to_ready(slot);
+                true
+            }
+            SlotState::InFlight => {
+                self.states[slot] = SlotState::Completed;
+                false
+            }
+            SlotState::Ready | SlotState::Completed => false,
+        }
+    }
+
+    /// Number of slots in the ready pool
+    pub fn num_ready(&self) -> usize {
+        self.ready.len()
+    }
+
+    /// Number of outstanding slots
+    pub fn num_outstanding(&self) -> usize {
+        self.num_outstanding
     }
 
-    pub fn return_in_flight_to_ready(&mut self, req: Box<Request<M, L>>) {
-        self.ready_pool.push_back(req);
+    /// Mark `num` taken slots as being submitted, so closing the context waits for them
+    pub fn start_submission(&mut self, num: usize) {
+        self.num_submitting += num;
     }
 
-    pub fn take(&mut self) -> Option<Box<Request<M, L>>> {
-        self.ready_pool.pop_front()
+    /// Mark `num` slots as submitted, or rejected by the kernel
+    pub fn finish_submission(&mut self, num: usize) {
+        self.num_submitting -= num;
+    }
+
+    /// Number of slots, which are taken for the submission, and not submitted yet
+    pub fn num_submitting(&self) -> usize {
+        self.num_submitting
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NoopLock;
+
+    #[test]
+    fn stale_completions_rejected() {
+        let slab = new_slab::<NoopLock>(2);
+        let request = &slab[1];
+
+        request.set_payload(-1, -1, &mut RawCommand::Fsync);
+        let stale = request.aio_data();
+        assert_eq!(1, slot_of(stale));
+        assert!(request.take_pending(stale));
+        // duplicate completion
+        assert!(!request.take_pending(stale));
+
+        request.set_payload(-1, -1, &mut RawCommand::Fsync);
+        assert_eq!(1, slot_of(request.aio_data()));
+        assert!(!request.take_pending(stale));
+        assert!(request.take_pending(request.aio_data()));
+    }
+
+    #[test]
+    fn dropped_slots_reclaimed_once() {
+        let mut requests = Requests::new(2);
+        let first = requests.take().unwrap();
+        let second = requests.take().unwrap();
+        assert!(requests.take().is_none());
+
+        // completed after its future is dropped
+        assert!(requests.move_to_outstanding(first));
+        assert_eq!(1, requests.num_outstanding());
+        assert!(requests.complete_unclaimed(first));
+        assert_eq!(0, requests.num_outstanding());
+
+        // completed while its future is being dropped
+        assert!(!requests.complete_unclaimed(second));
+        assert!(!requests.move_to_outstanding(second));
+        assert_eq!(0, requests.num_outstanding());
+
+        assert_eq!(2, requests.num_ready());
     }
 }
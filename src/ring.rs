@@ -0,0 +1,67 @@
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::aio;
+
+/// Completion ring of the AIO context, mapped to user space by the kernel
+///
+/// Only one reader may consume the ring, and `io_getevents` must not be
+/// used on the context at the same time.
+pub(crate) struct AioRing {
+    ring: *mut aio::aio_ring,
+}
+
+unsafe impl Send for AioRing {}
+
+impl AioRing {
+    /// Ring of the `context`. Return None if the ring layout is not known
+    ///
+    /// # Safety
+    ///
+    /// The context must stay alive while the ring is used
+    pub unsafe fn new(context: aio::aio_context_t) -> Option<AioRing> {
+        let ring = context as usize as *mut aio::aio_ring;
+        if ring.is_null() {
+            return None;
+        }
+
+        let header = ptr::read_volatile(ring);
+        if header.magic != aio::AIO_RING_MAGIC
+            || header.incompat_features != 0
+            || header.header_length as usize != mem::size_of::<aio::aio_ring>()
+            || header.nr == 0
+        {
+            return None;
+        }
+
+        Some(AioRing { ring })
+    }
+
+    /// Move the completed events from the ring to `events`, up to its capacity.
+    /// Return the number of moved events
+    pub fn reap(&mut self, events: &mut Vec<aio::io_event>) -> usize {
+        unsafe {
+            let nr = ptr::addr_of!((*self.ring).nr).read_volatile();
+            let head_ptr = AtomicU32::from_ptr(ptr::addr_of_mut!((*self.ring).head));
+            let tail =
+                AtomicU32::from_ptr(ptr::addr_of_mut!((*self.ring).tail)).load(Ordering::Acquire);
+            let io_events = ptr::addr_of!((*self.ring).io_events) as *const aio::io_event;
+
+            let mut head = head_ptr.load(Ordering::Relaxed);
+            if head >= nr || tail >= nr {
+                return 0;
+            }
+
+            let start = events.len();
+            while head != tail && events.len() < events.capacity() {
+                events.push(ptr::read_volatile(io_events.add(head as usize)));
+                head = (head + 1) % nr;
+            }
+
+            head_ptr.store(head, Ordering::Release);
+
+            events.len() - start
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::array;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{AioResult, aio};
+
+const NUM_LATENCY_BUCKETS: usize = 24;
+
+/// Type of AIO command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AioOpcode {
+    /// Read
+    Pread,
+    /// Write
+    Pwrite,
+    /// Sync data and metadata
+    Fsync,
+    /// Sync data only
+    Fdsync,
+    /// Wait for the readiness of the file descriptor
+    Poll,
+    /// Vectored read
+    Preadv,
+    /// Vectored write
+    Pwritev,
+}
+
+impl AioOpcode {
+    const ALL: [AioOpcode; 7] = [
+        AioOpcode::Pread,
+        AioOpcode::Pwrite,
+        AioOpcode::Fsync,
+        AioOpcode::Fdsync,
+        AioOpcode::Poll,
+        AioOpcode::Preadv,
+        AioOpcode::Pwritev,
+    ];
+
+    fn raw(self) -> u32 {
+        match self {
+            AioOpcode::Pread => aio::IOCB_CMD_PREAD,
+            AioOpcode::Pwrite => aio::IOCB_CMD_PWRITE,
+            AioOpcode::Fsync => aio::IOCB_CMD_FSYNC,
+            AioOpcode::Fdsync => aio::IOCB_CMD_FDSYNC,
+            AioOpcode::Poll => aio::IOCB_CMD_POLL,
+            AioOpcode::Preadv => aio::IOCB_CMD_PREADV,
+            AioOpcode::Pwritev => aio::IOCB_CMD_PWRITEV,
+        }
+    }
+
+    fn index_of_raw(raw: u16) -> usize {
+        Self::ALL
+            .iter()
+            .position(|opcode| opcode.raw() == raw as u32)
+            .expect("unknown AIO opcode")
+    }
+
+    #[cfg(feature = "tracing")]
+    pub(crate) fn from_raw(raw: u16) -> AioOpcode {
+        Self::ALL[Self::index_of_raw(raw)]
+    }
+}
+
+/// Histogram of request latencies, from the submission to the completion
+///
+/// Bucket boundaries are powers of two microseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: [u64; NUM_LATENCY_BUCKETS],
+}
+
+impl LatencyHistogram {
+    fn bucket_upper_bound(index: usize) -> Duration {
+        if index == NUM_LATENCY_BUCKETS - 1 {
+            Duration::MAX
+        } else {
+            Duration::from_micros(1 << index)
+        }
+    }
+
+    /// Total number of recorded latencies
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Buckets as pairs of the exclusive upper bound and the number of latencies
+    /// in the bucket. The last bucket is unbounded, and its upper bound is `Duration::MAX`
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(index, &count)| (Self::bucket_upper_bound(index), count))
+    }
+
+    /// Upper bound of the bucket, containing the `quantile` (from 0.0 to 1.0) of latencies.
+    /// Return None if nothing was recorded
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+
+        let rank = ((total as f64 * quantile).ceil() as u64).clamp(1, total);
+        let mut seen = 0;
+
+        self.buckets().find_map(|(upper_bound, count)| {
+            seen += count;
+            (seen >= rank).then_some(upper_bound)
+        })
+    }
+}
+
+/// Statistics of one type of AIO commands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AioOpcodeStats {
+    /// Type of the commands
+    pub opcode: AioOpcode,
+    /// Requests, passed to `io_submit`, including the rejected ones
+    pub submitted: u64,
+    /// Requests, completed successfully
+    pub completed: u64,
+    /// Requests, rejected by `io_submit` or completed with an error
+    pub failed: u64,
+    /// Requests, cancelled with `io_cancel`
+    pub cancelled: u64,
+    /// Latencies of completed and failed requests
+    pub latency: LatencyHistogram,
+}
+
+/// Snapshot of AIO context statistics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AioStats {
+    /// Total number of AIO slots in the context
+    pub num_slots: usize,
+    /// Slots, taken by the requests, which are not completed yet,
+    /// or whose results are not received yet
+    pub in_flight: usize,
+    /// In-flight requests, whose futures were dropped before the completion
+    pub outstanding: usize,
+    /// Available slots, as reported by `available_slots`
+    pub available_slots: Option<usize>,
+    /// Statistics for every type of AIO commands
+    pub opcodes: Vec<AioOpcodeStats>,
+}
+
+impl AioStats {
+    /// Statistics of the `opcode` commands
+    pub fn opcode(&self, opcode: AioOpcode) -> &AioOpcodeStats {
+        self.opcodes
+            .iter()
+            .find(|stats| stats.opcode == opcode)
+            .expect("no stats for the opcode")
+    }
+}
+
+#[derive(Debug, Default)]
+struct OpcodeCounters {
+    submitted: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    cancelled: AtomicU64,
+    latency: [AtomicU64; NUM_LATENCY_BUCKETS],
+}
+
+#[derive(Debug, Default)]
+pub struct StatsCounters {
+    opcodes: [OpcodeCounters; AioOpcode::ALL.len()],
+}
+
+impl StatsCounters {
+    fn counters(&self, raw_opcode: u16) -> &OpcodeCounters {
+        &self.opcodes[AioOpcode::index_of_raw(raw_opcode)]
+    }
+
+    pub fn record_submitted(&self, raw_opcode: u16, accepted: bool) {
+        let counters = self.counters(raw_opcode);
+
+        counters.submitted.fetch_add(1, Ordering::Relaxed);
+        if !accepted {
+            counters.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_completion(
+        &self,
+        raw_opcode: u16,
+        submitted_at: Option<Instant>,
+        cancelled: bool,
+        res: AioResult,
+    ) {
+        let counters = self.counters(raw_opcode);
+
+        if cancelled {
+            counters.cancelled.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        if res < 0 {
+            counters.failed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            counters.completed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(submitted_at) = submitted_at {
+            let micros = submitted_at.elapsed().as_micros();
+            let bucket =
+                ((u128::BITS - micros.leading_zeros()) as usize).min(NUM_LATENCY_BUCKETS - 1);
+            counters.latency[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<AioOpcodeStats> {
+        AioOpcode::ALL
+            .iter()
+            .zip(&self.opcodes)
+            .map(|(&opcode, counters)| AioOpcodeStats {
+                opcode,
+                submitted: counters.submitted.load(Ordering::Relaxed),
+                completed: counters.completed.load(Ordering::Relaxed),
+                failed: counters.failed.load(Ordering::Relaxed),
+                cancelled: counters.cancelled.load(Ordering::Relaxed),
+                latency: LatencyHistogram {
+                    counts: array::from_fn(|index| counters.latency[index].load(Ordering::Relaxed)),
+                },
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::{HashMap, VecDeque};
+use std::os::unix::prelude::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{io, mem, thread};
+
+use parking_lot::{Condvar, Mutex, MutexGuard};
+
+use crate::locked_buf::LifetimeExtender;
+use crate::requests::IoVec;
+use crate::{AioResult, aio};
+
+/// How often the blocking poll checks, whether it's cancelled
+const POLL_INTERVAL_MS: libc::c_int = 50;
+
+/// Limit of the dedicated threads per CPU core
+const WORKERS_PER_CORE: usize = 4;
+
+/// Executes the AIO control blocks as blocking system calls, on the blocking threads
+/// of tokio runtime, or on dedicated threads without it
+///
+/// Like with `IOCB_FLAG_RESFD`, every completion is signalled through eventfd.
+pub(crate) struct ThreadPool {
+    shared: Arc<Shared>,
+    /// Limit of the dedicated threads, which are started on demand
+    max_workers: usize,
+    #[cfg(feature = "tokio")]
+    runtime: Option<tokio::runtime::Handle>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    completed: Condvar,
+    /// Wakes up the idle dedicated threads
+    queued: Condvar,
+    /// Polls in flight by `user_data`, and whether they are cancelled
+    polls: Mutex<HashMap<u64, bool>>,
+    eventfd: OwnedFd,
+}
+
+#[derive(Default)]
+struct State {
+    completions: VecDeque<aio::io_event>,
+    /// Requests, which the threads didn't complete yet
+    in_flight: usize,
+    /// Jobs, waiting for the dedicated threads
+    jobs: VecDeque<Job>,
+    num_workers: usize,
+    idle_workers: usize,
+    /// The pool is dropped, so the dedicated threads exit
+    shutdown: bool,
+    /// Buffers of the requests, which the context released in flight. They are
+    /// freed with the last job
+    retained: Vec<(Vec<LifetimeExtender>, Vec<IoVec>)>,
+}
+
+impl ThreadPool {
+    /// Pool, which signals `eventfd` on completions. Without tokio runtime, up to
+    /// `nr` dedicated threads are started, but no more than `WORKERS_PER_CORE` per core
+    pub fn new(eventfd: BorrowedFd, nr: usize) -> io::Result<ThreadPool> {
+        let num_cores = thread::available_parallelism().map_or(1, |n| n.get());
+
+        Ok(ThreadPool {
+            shared: Arc::new(Shared {
+                state: Mutex::new(State::default()),
+                completed: Condvar::new(),
+                queued: Condvar::new(),
+                polls: Mutex::new(HashMap::new()),
+                eventfd: eventfd.try_clone_to_owned()?,
+            }),
+            max_workers: nr.clamp(1, num_cores * WORKERS_PER_CORE),
+            #[cfg(feature = "tokio")]
+            runtime: tokio::runtime::Handle::try_current().ok(),
+        })
+    }
+
+    /// Start the control blocks. All of them are accepted, and the failures are
+    /// reported with the completions
+    ///
+    /// # Safety
+    ///
+    /// The control blocks and the buffers they refer to must stay valid until completed
+    pub unsafe fn submit(&self, iocbs: &[*mut aio::iocb]) -> usize {
+        for &iocb in iocbs {
+            let job = Job {
+                shared: self.shared.clone(),
+                iocb: *iocb,
+                res: None,
+            };
+
+            if job.is_poll() {
+                self.shared.polls.lock().insert(job.iocb.aio_data, false);
+            }
+            self.shared.state.lock().in_flight += 1;
+
+            #[cfg(feature = "tokio")]
+            if let Some(runtime) = &self.runtime {
+                runtime.spawn_blocking(move || job.run());
+                continue;
+            }
+
+            self.queue(job);
+        }
+
+        iocbs.len()
+    }
+
+    /// Pass the job to the dedicated threads, starting a new one if all are busy
+    fn queue(&self, job: Job) {
+        let mut state = self.shared.state.lock();
+        state.jobs.push_back(job);
+
+        if state.jobs.len() > state.idle_workers && state.num_workers < self.max_workers {
+            let shared = self.shared.clone();
+            let spawned = thread::Builder::new()
+                .name("aio-blocking".into())
+                .spawn(move || work(&shared));
+
+            if spawned.is_ok() {
+                state.num_workers += 1;
+            } else if state.num_workers == 0 {
+                // no thread runs the job, so it's dropped and completed as cancelled
+                let job = state.jobs.pop_back();
+                mem::drop(state);
+                mem::drop(job);
+                return;
+            }
+        }
+
+        self.shared.queued.notify_one();
+    }
+
+    /// Cancel the control block. Only polls can be cancelled, they complete
+    /// with no events. Other system calls can't be interrupted. The poll, which is
+    /// already completed, delivers its result, and `ENOENT` error is returned
+    pub fn cancel(&self, iocb: &aio::iocb) -> io::Result<()> {
+        if iocb.aio_lio_opcode as u32 != aio::IOCB_CMD_POLL {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+
+        match self.shared.polls.lock().get_mut(&iocb.aio_data) {
+            Some(cancelled) => {
+                *cancelled = true;
+                Ok(())
+            }
+            None => Err(io::Error::from_raw_os_error(libc::ENOENT)),
+        }
+    }
+
+    /// Move the completions to `events`, up to its capacity, waiting for `min_nr`
+    /// of them up to `timeout`. Return the number of moved completions
+    pub fn get_events(
+        &self,
+        min_nr: usize,
+        timeout: Option<Duration>,
+        events: &mut Vec<aio::io_event>,
+    ) -> usize {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut state = self.shared.state.lock();
+
+        while state.completions.len() < min_nr {
+            match deadline {
+                Some(deadline) => {
+                    if self
+                        .shared
+                        .completed
+                        .wait_until(&mut state, deadline)
+                        .timed_out()
+                    {
+                        break;
+                    }
+                }
+                None => self.shared.completed.wait(&mut state),
+            }
+        }
+
+        events.clear();
+        let num_events = state.completions.len().min(events.capacity());
+        events.extend(state.completions.drain(..num_events));
+
+        num_events
+    }
+
+    /// Cancel the polls, without waiting for the requests in flight. Their
+    /// completions are discarded, and the buffers are kept until they finish
+    pub fn cancel_all(&self, buffers: impl Iterator<Item = (Vec<LifetimeExtender>, Vec<IoVec>)>) {
+        for cancelled in self.shared.polls.lock().values_mut() {
+            *cancelled = true;
+        }
+
+        let mut state = self.shared.state.lock();
+        state.completions.clear();
+        if state.in_flight > 0 {
+            state.retained.extend(buffers);
+        }
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        let jobs = {
+            let mut state = self.shared.state.lock();
+            state.shutdown = true;
+            mem::take(&mut state.jobs)
+        };
+        self.shared.queued.notify_all();
+
+        // the jobs, which didn't start, are completed as cancelled
+        mem::drop(jobs);
+    }
+}
+
+/// Run the queued jobs on the dedicated thread, until the pool is dropped
+fn work(shared: &Shared) {
+    let mut state = shared.state.lock();
+
+    loop {
+        if let Some(job) = state.jobs.pop_front() {
+            MutexGuard::unlocked(&mut state, || job.run());
+            continue;
+        }
+
+        if state.shutdown {
+            state.num_workers -= 1;
+            return;
+        }
+
+        state.idle_workers += 1;
+        shared.queued.wait(&mut state);
+        state.idle_workers -= 1;
+    }
+}
+
+/// Control block, executed on the blocking thread. It's completed when dropped,
+/// even if it never runs, e.g. because the runtime is shut down
+struct Job {
+    shared: Arc<Shared>,
+    iocb: aio::iocb,
+    res: Option<AioResult>,
+}
+
+// the buffers of the control block are kept by the request until it's completed
+unsafe impl Send for Job {}
+
+impl Job {
+    fn is_poll(&self) -> bool {
+        self.iocb.aio_lio_opcode as u32 == aio::IOCB_CMD_POLL
+    }
+
+    fn run(mut self) {
+        self.res = Some(unsafe { self.execute() });
+    }
+
+    unsafe fn execute(&self) -> AioResult {
+        let iocb = &self.iocb;
+        let fd = iocb.aio_fildes as RawFd;
+        let flags = iocb.aio_rw_flags as libc::c_int;
+
+        let single = libc::iovec {
+            iov_base: iocb.aio_buf as usize as *mut libc::c_void,
+            iov_len: iocb.aio_nbytes as usize,
+        };
+        let vectored = iocb.aio_buf as usize as *const libc::iovec;
+
+        let result = match iocb.aio_lio_opcode as u32 {
+            aio::IOCB_CMD_PREAD => libc::preadv2(fd, &single, 1, iocb.aio_offset, flags),
+            aio::IOCB_CMD_PWRITE => libc::pwritev2(fd, &single, 1, iocb.aio_offset, flags),
+            aio::IOCB_CMD_PREADV => libc::preadv2(
+                fd,
+                vectored,
+                iocb.aio_nbytes as libc::c_int,
+                iocb.aio_offset,
+                flags,
+            ),
+            aio::IOCB_CMD_PWRITEV => libc::pwritev2(
+                fd,
+                vectored,
+                iocb.aio_nbytes as libc::c_int,
+                iocb.aio_offset,
+                flags,
+            ),
+            aio::IOCB_CMD_FSYNC => libc::fsync(fd) as libc::ssize_t,
+            aio::IOCB_CMD_FDSYNC => libc::fdatasync(fd) as libc::ssize_t,
+            aio::IOCB_CMD_POLL => return self.poll(fd, iocb.aio_buf as libc::c_short),
+            opcode => unreachable!("unexpected AIO opcode {}", opcode),
+        };
+
+        if result < 0 {
+            -(io::Error::last_os_error()
+                .raw_os_error()
+                .unwrap_or(libc::EIO) as AioResult)
+        } else {
+            result as AioResult
+        }
+    }
+
+    /// Wait until `fd` is ready, checking for cancellation. Like with kernel AIO,
+    /// the cancelled poll completes with no events
+    unsafe fn poll(&self, fd: RawFd, events: libc::c_short) -> AioResult {
+        let mut pollfd = libc::pollfd {
+            fd,
+            events,
+            revents: 0,
+        };
+
+        loop {
+            if libc::poll(&mut pollfd, 1, POLL_INTERVAL_MS) < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return -(err.raw_os_error().unwrap_or(libc::EIO) as AioResult);
+                }
+            } else if pollfd.revents & libc::POLLNVAL != 0 {
+                return -libc::EBADF as AioResult;
+            } else if pollfd.revents != 0 {
+                return pollfd.revents as u16 as AioResult;
+            }
+
+            if self.shared.polls.lock().get(&self.iocb.aio_data) == Some(&true) {
+                return 0;
+            }
+        }
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        if self.is_poll() {
+            self.shared.polls.lock().remove(&self.iocb.aio_data);
+        }
+
+        {
+            let mut state = self.shared.state.lock();
+            state.completions.push_back(aio::io_event {
+                data: self.iocb.aio_data,
+                obj: 0,
+                res: self.res.unwrap_or(-libc::ECANCELED as AioResult),
+                res2: 0,
+            });
+            state.in_flight -= 1;
+        }
+        self.shared.completed.notify_all();
+
+        let one = 1u64.to_ne_bytes();
+        unsafe {
+            libc::write(
+                self.shared.eventfd.as_raw_fd(),
+                one.as_ptr() as *const libc::c_void,
+                mem::size_of::<u64>(),
+            )
+        };
+    }
+}
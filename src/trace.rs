@@ -0,0 +1,23 @@
+/// Emit the `tracing` event about the request, with its fd, opcode, offset, len
+/// and time in flight. Does nothing, unless the `tracing` feature is enabled
+#[cfg(feature = "tracing")]
+macro_rules! request_event {
+    ($level:ident, $request_inner:expr, $($fields:tt)+) => {{
+        let request_inner: &crate::requests::RequestInner = &$request_inner;
+        tracing::$level!(
+            fd = request_inner.aio_req.aio_fildes as i32,
+            opcode = ?crate::AioOpcode::from_raw(request_inner.aio_req.aio_lio_opcode),
+            offset = request_inner.aio_req.aio_offset,
+            len = request_inner.aio_req.aio_nbytes,
+            in_flight_us = request_inner
+                .submitted_at
+                .map(|submitted_at| submitted_at.elapsed().as_micros() as u64),
+            $($fields)+
+        )
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! request_event {
+    ($($args:tt)+) => {{}};
+}
@@ -0,0 +1,604 @@
+#![allow(non_camel_case_types)]
+
+use std::collections::HashMap;
+use std::os::unix::prelude::*;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use std::{io, mem, ptr};
+
+use lock_api::{Mutex, RawMutex};
+
+use crate::aio;
+
+/*
+ * extracted from https://elixir.bootlin.com/linux/latest/source/include/uapi/linux/io_uring.h
+ */
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+
+const IORING_FEAT_SINGLE_MMAP: u32 = 1 << 0;
+const IORING_FEAT_NODROP: u32 = 1 << 1;
+const IORING_FEAT_EXT_ARG: u32 = 1 << 8;
+
+const IORING_ENTER_GETEVENTS: u32 = 1 << 0;
+const IORING_ENTER_EXT_ARG: u32 = 1 << 3;
+
+const IORING_SQ_CQ_OVERFLOW: u32 = 1 << 1;
+
+const IORING_REGISTER_EVENTFD: u32 = 4;
+
+const IORING_OP_READV: u8 = 1;
+const IORING_OP_WRITEV: u8 = 2;
+const IORING_OP_FSYNC: u8 = 3;
+const IORING_OP_POLL_ADD: u8 = 6;
+const IORING_OP_ASYNC_CANCEL: u8 = 14;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+
+const IORING_FSYNC_DATASYNC: u32 = 1 << 0;
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct io_sqring_offsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct io_cqring_offsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct io_uring_params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: io_sqring_offsets,
+    cq_off: io_cqring_offsets,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct io_uring_sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    /// `rw_flags`, `fsync_flags` or `poll32_events`, depending on the opcode
+    op_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    __pad2: u64,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct io_uring_cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct io_uring_getevents_arg {
+    sigmask: u64,
+    sigmask_sz: u32,
+    pad: u32,
+    ts: u64,
+}
+
+unsafe fn io_uring_setup(entries: u32, params: *mut io_uring_params) -> libc::c_long {
+    libc::syscall(libc::SYS_io_uring_setup, entries, params)
+}
+
+unsafe fn io_uring_enter(
+    fd: RawFd,
+    to_submit: u32,
+    min_complete: u32,
+    flags: u32,
+    arg: *const io_uring_getevents_arg,
+) -> libc::c_long {
+    libc::syscall(
+        libc::SYS_io_uring_enter,
+        fd,
+        to_submit,
+        min_complete,
+        flags,
+        arg,
+        mem::size_of::<io_uring_getevents_arg>(),
+    )
+}
+
+unsafe fn io_uring_register(
+    fd: RawFd,
+    opcode: u32,
+    arg: *const libc::c_void,
+    nr_args: u32,
+) -> libc::c_long {
+    libc::syscall(libc::SYS_io_uring_register, fd, opcode, arg, nr_args)
+}
+
+/// `user_data` of the cancellations, whose results are not awaited
+const CANCEL_USER_DATA: u64 = u64::MAX;
+
+/// Set in `user_data` of the awaited cancellations, along with their sequence number.
+/// The generation in `aio_data` never reaches this bit
+const CANCEL_USER_DATA_TAG: u64 = 1 << 62;
+
+/// Set in `user_data` of the polls. The generation in `aio_data` never reaches this bit
+const POLL_USER_DATA_TAG: u64 = 1 << 63;
+
+/// Memory region, mapped from the ring file descriptor
+struct Mmap {
+    addr: *mut libc::c_void,
+    len: usize,
+}
+
+impl Mmap {
+    fn new(fd: BorrowedFd, offset: libc::off_t, len: usize) -> io::Result<Mmap> {
+        let addr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                offset,
+            )
+        };
+
+        if addr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Mmap { addr, len })
+    }
+
+    /// Pointer to the field at `offset`
+    fn at<T>(&self, offset: u32) -> *mut T {
+        unsafe { self.addr.add(offset as usize) as *mut T }
+    }
+}
+
+// the mapping is only accessed through the queues, which are guarded by mutexes
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.addr, self.len) };
+    }
+}
+
+struct SubmissionQueue {
+    head: *const AtomicU32,
+    tail: *const AtomicU32,
+    flags: *const AtomicU32,
+    mask: u32,
+    entries: u32,
+    array: *mut u32,
+    sqes: *mut io_uring_sqe,
+}
+
+struct CompletionQueue {
+    head: *const AtomicU32,
+    tail: *const AtomicU32,
+    mask: u32,
+    cqes: *const io_uring_cqe,
+    /// Completions of the requests, reaped while waiting for a cancellation
+    reaped: Vec<aio::io_event>,
+    /// Results of the awaited cancellations by `user_data`
+    cancelled: HashMap<u64, i32>,
+}
+
+unsafe impl Send for SubmissionQueue {}
+unsafe impl Send for CompletionQueue {}
+
+/// `io_uring` instance, which executes the AIO control blocks
+///
+/// Completions are signalled through the registered eventfd, like with `IOCB_FLAG_RESFD`.
+/// Only the completions of the requests are returned, the results of cancellations
+/// are skipped.
+pub(crate) struct IoUring<M: RawMutex> {
+    sq: Mutex<M, SubmissionQueue>,
+    cq: Mutex<M, CompletionQueue>,
+    /// Requests, accepted by the kernel and not reaped yet
+    in_flight: AtomicUsize,
+    /// Sequence number of the last awaited cancellation
+    cancel_seq: AtomicU64,
+    _sqes_mmap: Mmap,
+    _ring_mmap: Mmap,
+    fd: OwnedFd,
+}
+
+impl<M: RawMutex> IoUring<M> {
+    /// Set up the ring for `entries` requests in flight, signalling `eventfd`
+    /// on completions. Requires Linux 5.11+
+    pub fn new(entries: u32, eventfd: BorrowedFd) -> io::Result<IoUring<M>> {
+        let mut params = io_uring_params::default();
+
+        let fd = unsafe { io_uring_setup(entries, &mut params) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+
+        let required = IORING_FEAT_SINGLE_MMAP | IORING_FEAT_NODROP | IORING_FEAT_EXT_ARG;
+        if params.features & required != required {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "io_uring of the kernel is too old",
+            ));
+        }
+
+        let sq_ring_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_ring_len = params.cq_off.cqes as usize
+            + params.cq_entries as usize * mem::size_of::<io_uring_cqe>();
+        let ring_mmap = Mmap::new(fd.as_fd(), IORING_OFF_SQ_RING, sq_ring_len.max(cq_ring_len))?;
+        let sqes_mmap = Mmap::new(
+            fd.as_fd(),
+            IORING_OFF_SQES,
+            params.sq_entries as usize * mem::size_of::<io_uring_sqe>(),
+        )?;
+
+        let eventfd = eventfd.as_raw_fd();
+        let result = unsafe {
+            io_uring_register(
+                fd.as_raw_fd(),
+                IORING_REGISTER_EVENTFD,
+                &eventfd as *const RawFd as *const libc::c_void,
+                1,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let sq = unsafe {
+            SubmissionQueue {
+                head: ring_mmap.at(params.sq_off.head),
+                tail: ring_mmap.at(params.sq_off.tail),
+                flags: ring_mmap.at(params.sq_off.flags),
+                mask: *ring_mmap.at::<u32>(params.sq_off.ring_mask),
+                entries: *ring_mmap.at::<u32>(params.sq_off.ring_entries),
+                array: ring_mmap.at(params.sq_off.array),
+                sqes: sqes_mmap.at(0),
+            }
+        };
+        let cq = unsafe {
+            CompletionQueue {
+                head: ring_mmap.at(params.cq_off.head),
+                tail: ring_mmap.at(params.cq_off.tail),
+                mask: *ring_mmap.at::<u32>(params.cq_off.ring_mask),
+                cqes: ring_mmap.at(params.cq_off.cqes),
+                reaped: Vec::new(),
+                cancelled: HashMap::new(),
+            }
+        };
+
+        Ok(IoUring {
+            sq: Mutex::new(sq),
+            cq: Mutex::new(cq),
+            in_flight: AtomicUsize::new(0),
+            cancel_seq: AtomicU64::new(0),
+            _sqes_mmap: sqes_mmap,
+            _ring_mmap: ring_mmap,
+            fd,
+        })
+    }
+
+    /// Submit the control blocks. Like `io_submit`, return the number of the accepted
+    /// ones, or the error if none was accepted. The lengths, which don't fit
+    /// the submission queue entry, are rejected with `EINVAL`
+    ///
+    /// # Safety
+    ///
+    /// The control blocks and the buffers they refer to must stay valid until completed
+    pub unsafe fn submit(&self, iocbs: &[*mut aio::iocb]) -> io::Result<usize> {
+        let valid = iocbs
+            .iter()
+            .take_while(|&&iocb| (*iocb).aio_nbytes <= u32::MAX as u64)
+            .count();
+        if valid == 0 {
+            return Err(io::Error::from_raw_os_error(libc::EINVAL));
+        }
+
+        let accepted = self.push(iocbs[..valid].iter().map(|&iocb| sqe_from_iocb(&*iocb)))?;
+        self.in_flight.fetch_add(accepted, Ordering::Relaxed);
+
+        Ok(accepted)
+    }
+
+    /// Ask the kernel to cancel the submitted control block, and wait for the outcome.
+    /// If it's cancelled, the request is completed with `ECANCELED`. Otherwise, e.g.
+    /// if it's already completed or running, `ENOENT` or `EALREADY` error is returned
+    pub fn cancel(&self, iocb: &aio::iocb) -> io::Result<()> {
+        let seq = self.cancel_seq.fetch_add(1, Ordering::Relaxed);
+        let cancel_user_data = CANCEL_USER_DATA_TAG | (seq & (CANCEL_USER_DATA_TAG - 1));
+
+        self.push_cancel(iocb, cancel_user_data)?;
+
+        // the cancellation is usually completed during the submission
+        let mut cq = self.cq.lock();
+        loop {
+            if let Some(res) = cq.cancelled.remove(&cancel_user_data) {
+                return if res < 0 {
+                    Err(io::Error::from_raw_os_error(-res))
+                } else {
+                    Ok(())
+                };
+            }
+
+            let mut reaped = mem::take(&mut cq.reaped);
+            reaped.reserve(self.in_flight.load(Ordering::Relaxed).max(1));
+            self.reap(&mut cq, &mut reaped);
+            cq.reaped = reaped;
+
+            if !cq.cancelled.contains_key(&cancel_user_data) {
+                self.wait(1, None)?;
+            }
+        }
+    }
+
+    /// Ask the kernel to cancel the submitted control block, without waiting for
+    /// the outcome. It's known only from the completion of the request
+    pub fn cancel_nowait(&self, iocb: &aio::iocb) -> io::Result<()> {
+        self.push_cancel(iocb, CANCEL_USER_DATA)
+    }
+
+    fn push_cancel(&self, iocb: &aio::iocb, cancel_user_data: u64) -> io::Result<()> {
+        let sqe = io_uring_sqe {
+            opcode: IORING_OP_ASYNC_CANCEL,
+            addr: user_data(iocb),
+            user_data: cancel_user_data,
+            ..Default::default()
+        };
+
+        match self.push(std::iter::once(sqe))? {
+            0 => Err(io::Error::from_raw_os_error(libc::EAGAIN)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Place the entries to the submission queue, and submit them with `io_uring_enter`
+    fn push(&self, sqes: impl ExactSizeIterator<Item = io_uring_sqe>) -> io::Result<usize> {
+        let sq = self.sq.lock();
+
+        unsafe {
+            let head = (*sq.head).load(Ordering::Acquire);
+            let mut tail = (*sq.tail).load(Ordering::Relaxed);
+            let free = sq.entries - tail.wrapping_sub(head);
+
+            let mut to_submit = 0;
+            for sqe in sqes.take(free as usize) {
+                let index = tail & sq.mask;
+                sq.sqes.add(index as usize).write(sqe);
+                sq.array.add(index as usize).write(index);
+                tail = tail.wrapping_add(1);
+                to_submit += 1;
+            }
+            (*sq.tail).store(tail, Ordering::Release);
+
+            let result = io_uring_enter(self.fd.as_raw_fd(), to_submit, 0, 0, ptr::null());
+            let err = io::Error::last_os_error();
+
+            // the entries, which the kernel didn't consume, are dropped,
+            // so they are not submitted with the next call
+            let head = (*sq.head).load(Ordering::Acquire);
+            (*sq.tail).store(head, Ordering::Release);
+
+            if result < 0 {
+                Err(err)
+            } else {
+                Ok(result as usize)
+            }
+        }
+    }
+
+    /// Move the completions to `events`, up to its capacity, waiting for `min_nr`
+    /// of them up to `timeout`. Return the number of moved completions
+    pub fn get_events(
+        &self,
+        min_nr: usize,
+        timeout: Option<Duration>,
+        events: &mut Vec<aio::io_event>,
+    ) -> io::Result<usize> {
+        let mut cq = self.cq.lock();
+
+        events.clear();
+        let num_reaped = cq.reaped.len().min(events.capacity());
+        events.extend(cq.reaped.drain(..num_reaped));
+        self.reap(&mut cq, events);
+
+        if events.len() < min_nr && timeout != Some(Duration::ZERO) {
+            self.wait(min_nr - events.len(), timeout)?;
+            self.reap(&mut cq, events);
+        } else if self.overflown() {
+            // completions, which didn't fit the queue, are flushed to it by the kernel
+            self.wait(0, None)?;
+            self.reap(&mut cq, events);
+        }
+
+        Ok(events.len())
+    }
+
+    fn reap(&self, cq: &mut CompletionQueue, events: &mut Vec<aio::io_event>) {
+        unsafe {
+            let mut head = (*cq.head).load(Ordering::Relaxed);
+            let tail = (*cq.tail).load(Ordering::Acquire);
+
+            while head != tail && events.len() < events.capacity() {
+                let cqe = &*cq.cqes.add((head & cq.mask) as usize);
+                head = head.wrapping_add(1);
+
+                if cqe.user_data == CANCEL_USER_DATA {
+                    continue;
+                }
+                if cqe.user_data & CANCEL_USER_DATA_TAG != 0 {
+                    cq.cancelled.insert(cqe.user_data, cqe.res);
+                    continue;
+                }
+
+                let mut res = cqe.res as i64;
+                // like with kernel AIO, cancelled polls complete with no events
+                if cqe.user_data & POLL_USER_DATA_TAG != 0 && res == -libc::ECANCELED as i64 {
+                    res = 0;
+                }
+
+                events.push(aio::io_event {
+                    data: cqe.user_data & !POLL_USER_DATA_TAG,
+                    obj: 0,
+                    res,
+                    res2: 0,
+                });
+                self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            }
+
+            (*cq.head).store(head, Ordering::Release);
+        }
+    }
+
+    fn overflown(&self) -> bool {
+        let sq = self.sq.lock();
+
+        unsafe { (*sq.flags).load(Ordering::Acquire) & IORING_SQ_CQ_OVERFLOW != 0 }
+    }
+
+    fn wait(&self, min_complete: usize, timeout: Option<Duration>) -> io::Result<()> {
+        let ts = timeout.map(|timeout| aio::timespec {
+            tv_sec: timeout.as_secs() as _,
+            tv_nsec: timeout.subsec_nanos() as _,
+        });
+        let arg = io_uring_getevents_arg {
+            ts: ts.as_ref().map_or(0, |ts| ts as *const _ as u64),
+            ..Default::default()
+        };
+
+        let result = unsafe {
+            io_uring_enter(
+                self.fd.as_raw_fd(),
+                0,
+                min_complete as u32,
+                IORING_ENTER_GETEVENTS | IORING_ENTER_EXT_ARG,
+                &arg,
+            )
+        };
+
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            // the caller returns what's already completed
+            if !matches!(err.raw_os_error(), Some(libc::ETIME | libc::EINTR)) {
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cancel the control blocks at `iocbs`, and wait until all requests in flight
+    /// are completed. Their completions are discarded
+    pub fn cancel_all(&self, iocbs: impl ExactSizeIterator<Item = *const aio::iocb>) {
+        if self.in_flight.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let mut events = Vec::with_capacity(iocbs.len());
+        for iocb in iocbs {
+            let _ = self.push_cancel(unsafe { &*iocb }, CANCEL_USER_DATA);
+        }
+
+        while self.in_flight.load(Ordering::Relaxed) > 0 {
+            if self.get_events(1, None, &mut events).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// `user_data` of the submission queue entry for the control block
+fn user_data(iocb: &aio::iocb) -> u64 {
+    if iocb.aio_lio_opcode as u32 == aio::IOCB_CMD_POLL {
+        iocb.aio_data | POLL_USER_DATA_TAG
+    } else {
+        iocb.aio_data
+    }
+}
+
+/// Translate the AIO control block to the submission queue entry
+fn sqe_from_iocb(iocb: &aio::iocb) -> io_uring_sqe {
+    let mut sqe = io_uring_sqe {
+        fd: iocb.aio_fildes as i32,
+        off: iocb.aio_offset as u64,
+        addr: iocb.aio_buf,
+        len: iocb.aio_nbytes as u32,
+        // HIPRI is supported only by the rings, polling for all completions
+        op_flags: iocb.aio_rw_flags as u32 & !aio::RWF_HIPRI,
+        user_data: user_data(iocb),
+        ..Default::default()
+    };
+
+    match iocb.aio_lio_opcode as u32 {
+        aio::IOCB_CMD_PREAD => sqe.opcode = IORING_OP_READ,
+        aio::IOCB_CMD_PWRITE => sqe.opcode = IORING_OP_WRITE,
+        aio::IOCB_CMD_PREADV => sqe.opcode = IORING_OP_READV,
+        aio::IOCB_CMD_PWRITEV => sqe.opcode = IORING_OP_WRITEV,
+        aio::IOCB_CMD_FSYNC | aio::IOCB_CMD_FDSYNC => {
+            sqe.opcode = IORING_OP_FSYNC;
+            sqe.off = 0;
+            sqe.len = 0;
+            sqe.op_flags = if iocb.aio_lio_opcode as u32 == aio::IOCB_CMD_FDSYNC {
+                IORING_FSYNC_DATASYNC
+            } else {
+                0
+            };
+        }
+        aio::IOCB_CMD_POLL => {
+            // the events are passed in place of the buffer address
+            let events = iocb.aio_buf as u32;
+            sqe.opcode = IORING_OP_POLL_ADD;
+            // the kernel swaps the halves of poll32_events on big-endian
+            sqe.op_flags = if cfg!(target_endian = "big") {
+                events.rotate_left(16)
+            } else {
+                events
+            };
+            sqe.off = 0;
+            sqe.addr = 0;
+            sqe.len = 0;
+        }
+        opcode => unreachable!("unexpected AIO opcode {}", opcode),
+    }
+
+    sqe
+}
@@ -1,95 +1,233 @@
-use std::mem;
+use std::marker::PhantomData;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll, ready};
+use std::{fmt, io};
 
-use intrusive_collections::DefaultLinkOps;
-use lock_api::RawMutex;
 use std::future::Future;
-use tokio::sync::oneshot;
 
 use crate::errors::AioCommandError;
-use crate::requests::Request;
-use crate::{AioResult, GenericAioContextInner};
-use intrusive_collections::linked_list::LinkedListOps;
-
-pub(crate) struct AioWaitFuture<
-    M: RawMutex,
-    A: crate::IntrusiveAdapter<M, L>,
-    L: DefaultLinkOps<Ops = A::LinkOps> + Default,
-> where
-    A::LinkOps: LinkedListOps + Default,
-{
-    rx: oneshot::Receiver<AioResult>,
-    inner_context: Arc<GenericAioContextInner<M, A, L>>,
-    request: Option<Box<Request<M, L>>>,
+use crate::requests::{Completion, Request};
+use crate::{AioLock, AioResult, GenericAioContextInner};
+
+pub(crate) struct AioWaitFuture<M: AioLock> {
+    inner_context: Arc<GenericAioContextInner<M>>,
+    slot: Option<usize>,
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    AioWaitFuture<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> AioWaitFuture<M> {
     fn return_request_to_pool(&mut self) {
-        let req = self.request.take().unwrap();
-        mem::drop(req.inner.lock().take_buf_lifetime_extender());
+        let slot = self.slot.take().unwrap();
+        let request = &self.inner_context.slots[slot];
+        request.inner.lock().clear_buf_lifetime_extenders();
         self.inner_context
             .requests
             .lock()
-            .return_in_flight_to_ready(req);
+            .return_in_flight_to_ready(slot);
 
         if let Some(c) = &self.inner_context.capacity {
             c.add_permits(1)
         }
     }
 
-    pub fn new(
-        inner_context: &Arc<GenericAioContextInner<M, A, L>>,
-        rx: oneshot::Receiver<AioResult>,
-        request: Box<Request<M, L>>,
-    ) -> Self {
+    /// Ask the kernel to cancel the in-flight request
+    ///
+    /// On success, the cancellation result will be received by the future
+    pub fn cancel(&self) -> Result<(), AioCommandError> {
+        let request = match self.slot {
+            Some(slot) => &self.inner_context.slots[slot],
+            None => return Ok(()),
+        };
+
+        if let Some(res) = io_cancel(&self.inner_context, request)? {
+            self.inner_context.complete(request.aio_data(), res);
+            self.inner_context.completed.notify_waiters();
+        }
+
+        Ok(())
+    }
+
+    pub fn new(inner_context: &Arc<GenericAioContextInner<M>>, slot: usize) -> Self {
         AioWaitFuture {
-            rx,
             inner_context: inner_context.clone(),
-            request: Some(request),
+            slot: Some(slot),
         }
     }
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    Future for AioWaitFuture<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> Future for AioWaitFuture<M> {
     type Output = Result<AioResult, AioCommandError>;
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        let res = ready!(Pin::new(&mut self.rx).poll(cx))
-            .expect("AIO stopped while AioWaitFuture was not completed");
-        self.return_request_to_pool();
+        let slot = self.slot.expect("AioWaitFuture polled after completion");
+
+        match ready!(self.inner_context.slots[slot].completion.poll(cx)) {
+            Some(res) => {
+                self.return_request_to_pool();
+
+                Poll::Ready(Ok(res))
+            }
+            None => {
+                // the poller is stopped, while the request may still be in flight,
+                // so it is kept with the buffers until the context is destroyed
+                let slot = self.slot.take().unwrap();
+                self.inner_context.requests.lock().move_to_outstanding(slot);
 
-        Poll::Ready(Ok(res))
+                Poll::Ready(Err(self.inner_context.stopped_error()))
+            }
+        }
     }
 }
 
-impl<M: RawMutex, A: crate::IntrusiveAdapter<M, L>, L: DefaultLinkOps<Ops = A::LinkOps> + Default>
-    Drop for AioWaitFuture<M, A, L>
-where
-    A::LinkOps: LinkedListOps + Default,
-{
+impl<M: AioLock> Drop for AioWaitFuture<M> {
     fn drop(&mut self) {
-        self.rx.close();
+        let completed = self
+            .slot
+            .is_some_and(|slot| self.inner_context.slots[slot].completion.close().is_some());
 
-        if self.rx.try_recv().is_ok() {
-            // the sender have successfully sent data to the channel, but we didn't accept it
+        if completed {
+            // the poller has successfully delivered the result, but we didn't accept it
             self.return_request_to_pool();
         }
 
-        if let Some(in_flight) = self.request.take() {
-            self.inner_context
-                .requests
-                .lock()
-                .move_to_outstanding(in_flight)
+        if let Some(slot) = self.slot.take() {
+            let inner_context = &*self.inner_context;
+            let in_flight = &inner_context.slots[slot];
+
+            // Keep the pool locked, so the poller can't reclaim the request
+            // until it is outstanding
+            let mut requests = inner_context.requests.lock();
+
+            match io_cancel_nowait(inner_context, in_flight) {
+                Ok(Some(res)) => {
+                    request_event!(
+                        trace,
+                        in_flight.inner.lock(),
+                        res,
+                        "AIO request dropped in flight and cancelled"
+                    );
+                    in_flight.record_completion(&inner_context.stats, res);
+                    in_flight.inner.lock().pending = false;
+                    requests.return_in_flight_to_ready(slot);
+                    inner_context.release_request(in_flight);
+                }
+                #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                cancel_result => {
+                    request_event!(
+                        debug,
+                        in_flight.inner.lock(),
+                        cancel = ?cancel_result,
+                        "AIO request dropped in flight, left in outstanding"
+                    );
+                    // the poller has already failed to deliver the completion
+                    if !requests.move_to_outstanding(slot) {
+                        inner_context.release_request(in_flight);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Try to cancel the request with `io_cancel`, or its io_uring counterpart.
+///
+/// Returns `Ok(None)` if cancellation is in progress, and the completion event will be
+/// delivered through the ring buffer. Legacy kernels return the event directly, in which
+/// case `Ok(Some(res))` is returned, and no completion will be delivered through the ring.
+pub(crate) fn io_cancel<M: AioLock>(
+    inner_context: &GenericAioContextInner<M>,
+    request: &Request<M>,
+) -> Result<Option<AioResult>, AioCommandError> {
+    inner_context
+        .backend
+        .cancel(request.aio_iocb_ptr())
+        .map_err(AioCommandError::IoCancel)
+}
+
+/// Same as `io_cancel`, but doesn't wait for the outcome of the io_uring cancellation.
+/// Used, where blocking is not allowed: on drop and on close
+pub(crate) fn io_cancel_nowait<M: AioLock>(
+    inner_context: &GenericAioContextInner<M>,
+    request: &Request<M>,
+) -> Result<Option<AioResult>, AioCommandError> {
+    inner_context
+        .backend
+        .cancel_nowait(request.aio_iocb_ptr())
+        .map_err(AioCommandError::IoCancel)
+}
+
+/// Future, which resolves to the result of a submitted AIO command
+///
+/// Returned by [`submit_batch`]. The buffers referenced by the command remain borrowed
+/// until the future is resolved or dropped.
+///
+/// [`submit_batch`]: struct.GenericAioContextHandle.html#method.submit_batch
+pub struct AioRequestFuture<'a, M: AioLock> {
+    state: Option<Result<AioWaitFuture<M>, AioCommandError>>,
+    _buffers: PhantomData<&'a mut ()>,
+}
+
+impl<M: AioLock> AioRequestFuture<'_, M> {
+    pub(crate) fn new(wait_future: AioWaitFuture<M>) -> Self {
+        AioRequestFuture {
+            state: Some(Ok(wait_future)),
+            _buffers: PhantomData,
+        }
+    }
+
+    /// Ask the kernel to cancel the request.
+    ///
+    /// If the cancellation succeeds, the future resolves to the `BadResult` error with
+    /// `ECANCELED` code, or to zero events for `Poll`. The kernel may refuse to cancel the request, e.g. if it is
+    /// already completed, or if the operation doesn't support cancellation. In this case
+    /// the `IoCancel` error is returned, and the future resolves with the regular result.
+    pub fn cancel(&self) -> Result<(), AioCommandError> {
+        match &self.state {
+            Some(Ok(wait_future)) => wait_future.cancel(),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn failed(err: AioCommandError) -> Self {
+        AioRequestFuture {
+            state: Some(Err(err)),
+            _buffers: PhantomData,
         }
     }
 }
+
+impl<M: AioLock> Future for AioRequestFuture<'_, M> {
+    type Output = Result<u64, AioCommandError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let code = match self
+            .state
+            .as_mut()
+            .expect("AioRequestFuture polled after completion")
+        {
+            Ok(wait_future) => ready!(Pin::new(wait_future).poll(cx))?,
+            Err(_) => match self.state.take() {
+                Some(Err(e)) => return Poll::Ready(Err(e)),
+                _ => unreachable!(),
+            },
+        };
+
+        self.state = None;
+
+        if code < 0 {
+            Poll::Ready(Err(AioCommandError::BadResult(
+                io::Error::from_raw_os_error(-code as _),
+            )))
+        } else {
+            Poll::Ready(Ok(code.try_into().unwrap()))
+        }
+    }
+}
+
+impl<M: AioLock> fmt::Debug for AioRequestFuture<'_, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AioRequestFuture")
+            .field("completed", &self.state.is_none())
+            .finish()
+    }
+}
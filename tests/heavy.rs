@@ -1,15 +1,18 @@
 use std::fs::OpenOptions;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use rand::{Rng, thread_rng};
-use tempfile::tempdir;
+use tempfile::{tempdir, tempdir_in};
 use tokio::task::JoinSet;
 use tokio::time::sleep;
 
 use helpers::*;
 use linux_aio_tokio::AioOpenOptionsExt;
-use linux_aio_tokio::{LockedBuf, ReadFlags, WriteFlags, aio_context};
+use linux_aio_tokio::{
+    AioContextOptions, CompletionMode, File, LockedBuf, ReadFlags, WriteFlags, aio_context,
+    aio_context_with_options,
+};
 
 const PAGE_SIZE: usize = 1024 * 1024;
 const NUM_PAGES: usize = 256;
@@ -194,3 +197,83 @@ async fn read_many_blocks_mt() {
 
     dir.close().unwrap();
 }
+
+/// Compares the read latencies of `Polled` and `EventFd` completion modes. Only
+/// reports them, since they depend on the machine: `cargo test -- --ignored --nocapture`
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "benchmark"]
+async fn polled_latency_benchmark() {
+    const FILE_SIZE: usize = 1024 * 1024;
+    const BLOCK_SIZE: usize = 4096;
+    const NUM_READS: usize = 20_000;
+
+    // tmpfs keeps the device out of the measurement
+    let dir = tempdir_in("/dev/shm").or_else(|_| tempdir()).unwrap();
+    let path = dir.path().join("tmp");
+    fill_temp_file(FILE_SIZE, &mut std::fs::File::create(&path).unwrap());
+
+    let mut medians = Vec::new();
+
+    for (name, completion_mode) in [
+        ("eventfd", CompletionMode::EventFd),
+        (
+            "polled",
+            CompletionMode::Polled {
+                spin: Duration::from_micros(50),
+            },
+        ),
+    ] {
+        let (aio, aio_handle) =
+            aio_context_with_options(AioContextOptions::new(1).completion_mode(completion_mode))
+                .unwrap();
+
+        let file = File::open(&path, false).await.unwrap();
+
+        // requests are sent from a runtime worker, which is shared with the poller
+        let mut latencies = tokio::spawn(async move {
+            let mut buffer = LockedBuf::with_size(BLOCK_SIZE).unwrap();
+            let mut latencies = Vec::with_capacity(NUM_READS);
+
+            for index in 0..NUM_READS {
+                let started_at = Instant::now();
+
+                let res = file
+                    .read_at(
+                        &aio_handle,
+                        (index * BLOCK_SIZE % FILE_SIZE) as u64,
+                        &mut buffer,
+                        BLOCK_SIZE as _,
+                        ReadFlags::HIPRI,
+                    )
+                    .await
+                    .unwrap();
+
+                latencies.push(started_at.elapsed());
+                assert_eq!(BLOCK_SIZE, res as usize);
+            }
+
+            latencies
+        })
+        .await
+        .unwrap();
+
+        latencies.sort();
+        eprintln!(
+            "{} mode: p50 {:?}, p99 {:?}, max {:?}",
+            name,
+            latencies[NUM_READS / 2],
+            latencies[NUM_READS * 99 / 100],
+            latencies[NUM_READS - 1],
+        );
+        medians.push(latencies[NUM_READS / 2]);
+
+        aio.close().await;
+    }
+
+    eprintln!(
+        "polled p50 is {:.2} of eventfd p50",
+        medians[1].as_secs_f64() / medians[0].as_secs_f64()
+    );
+
+    dir.close().unwrap();
+}
@@ -6,22 +6,28 @@
 )]
 
 use std::fs::{OpenOptions, Permissions};
-use std::io::{Read, Seek, SeekFrom};
-use std::mem;
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::os::unix::prelude::*;
 use std::sync::Arc;
 use std::time::Duration;
+use std::{iter, mem};
 
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, Interest};
 use tokio::sync::oneshot;
 use tokio::task::{self, LocalSet};
-use tokio::time::sleep;
+use tokio::time::{Instant, sleep};
 
 use assert_matches::assert_matches;
 use helpers::*;
 use linux_aio_tokio::{
-    AioCommandError, LockedBuf, ReadFlags, WriteFlags, aio_context, local_aio_context,
+    AioBackend, AioCommandError, AioContext, AioContextError, AioContextHandle, AioContextOptions,
+    AioContextPool, AioFallback, AioOpcode, CloseReport, CompletionMode,
+    LOCKED_BUF_SLICE_ALIGNMENT, LockedBuf, LockedBufError, LockedBufPool, LockedBufPoolStats,
+    PollFlags, PollerHealth, RawCommand, ReadFlags, ShardRouting, WriteFlags, aio_context,
+    aio_context_with_options, aio_context_with_reaper, generic_aio_context, local_aio_context,
 };
-use linux_aio_tokio::{AioOpenOptionsExt, File};
+use linux_aio_tokio::{AioOpenOptionsExt, File, FileStream};
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -284,8 +290,7 @@ async fn read_block_mt() {
 }
 
 #[tokio::test]
-#[should_panic]
-async fn panic_on_wrong_len() {
+async fn error_on_wrong_len() {
     let (dir, path) = create_filled_tempfile(FILE_SIZE);
 
     {
@@ -296,17 +301,48 @@ async fn panic_on_wrong_len() {
 
         let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
 
-        let (_aio, aio_handle) = aio_context(1, true).unwrap();
+        let (aio, aio_handle) = aio_context(1, true).unwrap();
 
+        assert_matches!(
+            file.read_at(
+                &aio_handle,
+                0,
+                &mut buffer,
+                (BUF_CAPACITY + 1) as _,
+                ReadFlags::empty(),
+            )
+            .await,
+            Err(AioCommandError::InvalidLength { len, buffer_size })
+                if len == (BUF_CAPACITY + 1) as u64 && buffer_size == BUF_CAPACITY as u64
+        );
+
+        assert_matches!(
+            aio_handle
+                .submit_request(
+                    &file,
+                    RawCommand::Pwrite {
+                        offset: 0,
+                        buffer: &buffer,
+                        flags: WriteFlags::empty(),
+                        len: (BUF_CAPACITY * 2) as u64,
+                    },
+                )
+                .await,
+            Err(AioCommandError::InvalidLength { .. })
+        );
+
+        // the context is still usable
+        assert_eq!(Some(1), aio.available_slots());
         file.read_at(
             &aio_handle,
             0,
             &mut buffer,
-            (BUF_CAPACITY + 1) as _,
+            BUF_CAPACITY as _,
             ReadFlags::empty(),
         )
         .await
         .unwrap();
+        assert!(validate_block(buffer.as_ref()));
     }
 
     dir.close().unwrap();
@@ -699,3 +735,1872 @@ async fn mixed_read_write_at() {
 
     dir.close().unwrap();
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn submit_batch() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    let num_slots = 8;
+    let (aio, aio_handle) = aio_context(num_slots, true).unwrap();
+
+    let mut buffers: Vec<_> = (0..num_slots)
+        .map(|_| LockedBuf::with_size(BUF_CAPACITY).unwrap())
+        .collect();
+
+    let futures = aio_handle
+        .submit_batch(buffers.iter_mut().enumerate().map(|(index, buffer)| {
+            (
+                &file,
+                RawCommand::Pread {
+                    offset: (index * BUF_CAPACITY) as u64,
+                    buffer,
+                    flags: ReadFlags::empty(),
+                    len: BUF_CAPACITY as u64,
+                },
+            )
+        }))
+        .await
+        .unwrap();
+
+    assert_eq!(num_slots, futures.len());
+
+    for future in futures {
+        assert_eq!(BUF_CAPACITY as u64, future.await.unwrap());
+    }
+
+    for buffer in &buffers {
+        assert!(validate_block(buffer.as_ref()));
+    }
+
+    assert_eq!(num_slots, aio.available_slots().unwrap());
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn submit_batch_partial() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    let num_slots = 4;
+    let (aio, aio_handle) = aio_context(num_slots, true).unwrap();
+
+    let mut buffers: Vec<_> = (0..3)
+        .map(|_| LockedBuf::with_size(BUF_CAPACITY).unwrap())
+        .collect();
+
+    // the second command refers to the invalid fd, so io_submit accepts only the first one
+    let futures = aio_handle
+        .submit_batch(buffers.iter_mut().enumerate().map(|(index, buffer)| {
+            (
+                if index == 1 { -1 } else { file.as_raw_fd() },
+                RawCommand::Pread {
+                    offset: 0,
+                    buffer,
+                    flags: ReadFlags::empty(),
+                    len: BUF_CAPACITY as u64,
+                },
+            )
+        }))
+        .await
+        .unwrap();
+
+    let mut results = vec![];
+    for future in futures {
+        results.push(future.await);
+    }
+
+    assert_eq!(BUF_CAPACITY as u64, *results[0].as_ref().unwrap());
+    assert_matches!(results[1], Err(AioCommandError::IoSubmit(_)));
+    assert_eq!(BUF_CAPACITY as u64, *results[2].as_ref().unwrap());
+
+    assert!(validate_block(buffers[0].as_ref()));
+    assert!(validate_block(buffers[2].as_ref()));
+
+    assert_eq!(num_slots, aio.available_slots().unwrap());
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn vectored_read_write() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let mut open_options = OpenOptions::new();
+    open_options.read(true).write(true);
+
+    let file = open_options.aio_open(path.clone(), false).await.unwrap();
+
+    let (aio, aio_handle) = aio_context(2, true).unwrap();
+
+    let mut buffers = vec![
+        LockedBuf::with_size(4096).unwrap(),
+        LockedBuf::with_size(BUF_CAPACITY).unwrap(),
+    ];
+
+    let read_bytes = file
+        .read_vectored_at(&aio_handle, 0, &mut buffers, ReadFlags::empty())
+        .await
+        .unwrap();
+    assert_eq!(4096 + BUF_CAPACITY as u64, read_bytes);
+    assert!(validate_block(buffers[0].as_ref()));
+    // 4096 is a multiple of 256, so the pattern continues from zero
+    assert!(validate_block(buffers[1].as_ref()));
+
+    fill_pattern(1u8, buffers[0].as_mut());
+    fill_pattern(2u8, buffers[1].as_mut());
+
+    let wrote_bytes = file
+        .write_vectored_at(&aio_handle, 16384, &buffers, WriteFlags::DSYNC)
+        .await
+        .unwrap();
+    assert_eq!(4096 + BUF_CAPACITY as u64, wrote_bytes);
+
+    assert_eq!(2, aio.available_slots().unwrap());
+
+    let mut file = std::fs::File::open(&path).unwrap();
+
+    let mut header = [0u8; 4096];
+    let mut payload = [0u8; BUF_CAPACITY];
+
+    file.seek(SeekFrom::Start(16384)).unwrap();
+    file.read_exact(&mut header).unwrap();
+    file.read_exact(&mut payload).unwrap();
+    assert!(validate_pattern(1u8, &header));
+    assert!(validate_pattern(2u8, &payload));
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn vectored_slices() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let mut open_options = OpenOptions::new();
+    open_options.read(true).write(true);
+
+    let file = open_options.aio_open(path.clone(), false).await.unwrap();
+
+    let (aio, aio_handle) = aio_context(2, true).unwrap();
+
+    let arena = LockedBuf::with_size(2 * BUF_CAPACITY).unwrap();
+    let mut chunks = arena.into_slice().chunks(BUF_CAPACITY).unwrap();
+
+    let read_bytes = file
+        .read_vectored_at(&aio_handle, 0, &mut chunks, ReadFlags::empty())
+        .await
+        .unwrap();
+    assert_eq!(2 * BUF_CAPACITY as u64, read_bytes);
+    for chunk in &chunks {
+        assert!(validate_block(chunk.as_ref()));
+    }
+
+    // the buffers of different types are mixed in the raw command
+    let mut header = LockedBuf::with_size(4096).unwrap();
+    fill_pattern(1u8, header.as_mut());
+    fill_pattern(2u8, chunks[0].as_mut());
+
+    let wrote_bytes = aio_handle
+        .submit_request(
+            &file,
+            RawCommand::Pwritev {
+                offset: 16384,
+                buffers: &[&header, &chunks[0]],
+                flags: WriteFlags::DSYNC,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(4096 + BUF_CAPACITY as u64, wrote_bytes);
+
+    assert_eq!(2, aio.available_slots().unwrap());
+
+    let mut file = std::fs::File::open(&path).unwrap();
+
+    let mut header = [0u8; 4096];
+    let mut payload = [0u8; BUF_CAPACITY];
+
+    file.seek(SeekFrom::Start(16384)).unwrap();
+    file.read_exact(&mut header).unwrap();
+    file.read_exact(&mut payload).unwrap();
+    assert!(validate_pattern(1u8, &header));
+    assert!(validate_pattern(2u8, &payload));
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn explicit_cancel() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    let num_slots = 4;
+    let (aio, aio_handle) = aio_context(num_slots, true).unwrap();
+
+    let mut buffers: Vec<_> = (0..num_slots)
+        .map(|_| LockedBuf::with_size(BUF_CAPACITY).unwrap())
+        .collect();
+
+    let futures = aio_handle
+        .submit_batch(buffers.iter_mut().enumerate().map(|(index, buffer)| {
+            (
+                &file,
+                RawCommand::Pread {
+                    offset: (index * BUF_CAPACITY) as u64,
+                    buffer,
+                    flags: ReadFlags::empty(),
+                    len: BUF_CAPACITY as u64,
+                },
+            )
+        }))
+        .await
+        .unwrap();
+
+    for future in futures {
+        // regular file reads are usually not cancellable, so both outcomes are valid
+        match future.cancel() {
+            Ok(()) | Err(AioCommandError::IoCancel(_)) => {}
+            Err(e) => panic!("unexpected cancel error: {}", e),
+        }
+
+        match future.await {
+            Ok(bytes) => assert_eq!(BUF_CAPACITY as u64, bytes),
+            Err(AioCommandError::BadResult(e)) => {
+                assert_eq!(Some(libc::ECANCELED), e.raw_os_error())
+            }
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    assert_eq!(num_slots, aio.available_slots().unwrap());
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_with_deadline() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    let (aio, aio_handle) = aio_context(1, true).unwrap();
+
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    let read_bytes = file
+        .read_at_with_deadline(
+            &aio_handle,
+            0,
+            &mut buffer,
+            BUF_CAPACITY as _,
+            ReadFlags::empty(),
+            Instant::now() + Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+    assert_eq!(BUF_CAPACITY as u64, read_bytes);
+    assert!(validate_block(buffer.as_ref()));
+
+    // the request already passed its deadline, but it may still land
+    match file
+        .read_at_with_deadline(
+            &aio_handle,
+            0,
+            &mut buffer,
+            BUF_CAPACITY as _,
+            ReadFlags::empty(),
+            Instant::now(),
+        )
+        .await
+    {
+        Ok(bytes) => assert_eq!(BUF_CAPACITY as u64, bytes),
+        Err(AioCommandError::TimedOut { .. }) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+
+    while aio.available_slots().unwrap() != 1 {
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fsync_completed_by_deadline() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    for backend in [AioBackend::KernelAio, AioBackend::IoUring] {
+        let (aio, aio_handle) =
+            aio_context_with_options(AioContextOptions::new(1).backend(backend)).unwrap();
+
+        // the fsync, which lands by the 0ms deadline, is not reported as cancelled
+        let mut completed = false;
+        for _ in 0..100 {
+            match aio_handle
+                .submit_request_with_deadline(&file, RawCommand::Fsync, Instant::now())
+                .await
+            {
+                Ok(res) => {
+                    assert_eq!(0, res);
+                    completed = true;
+                }
+                Err(AioCommandError::TimedOut { cancelled: false }) => {}
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        assert!(completed);
+
+        aio.close().await;
+    }
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deadline_while_waiting_for_slot() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    let (aio, aio_handle) = aio_context(1, true).unwrap();
+
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+    let mut other_buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    // the slot is held until the future is resolved
+    let occupying = aio_handle
+        .submit_batch(iter::once((
+            &file,
+            RawCommand::Pread {
+                offset: 0,
+                buffer: &mut buffer,
+                flags: ReadFlags::empty(),
+                len: BUF_CAPACITY as u64,
+            },
+        )))
+        .await
+        .unwrap();
+
+    assert_matches!(
+        file.read_at_with_deadline(
+            &aio_handle,
+            0,
+            &mut other_buffer,
+            BUF_CAPACITY as _,
+            ReadFlags::empty(),
+            Instant::now() + Duration::from_millis(50),
+        )
+        .await,
+        Err(AioCommandError::TimedOut { cancelled: true })
+    );
+
+    for future in occupying {
+        future.await.unwrap();
+    }
+
+    assert_eq!(1, aio.available_slots().unwrap());
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn poll_pipe() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, mut write_end) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle) = aio_context(2, true).unwrap();
+
+    // writing end is immediately ready
+    let revents = aio_handle
+        .poll_fd(&write_end, PollFlags::OUT)
+        .await
+        .unwrap();
+    assert!(revents.contains(PollFlags::OUT));
+
+    let readable = tokio::spawn({
+        let aio_handle = aio_handle.clone();
+        async move { aio_handle.poll_fd(&read_end, PollFlags::IN).await }
+    });
+
+    sleep(Duration::from_millis(50)).await;
+    assert!(!readable.is_finished());
+
+    write_end.write_all(b"ping").unwrap();
+
+    let revents = readable.await.unwrap().unwrap();
+    assert!(revents.contains(PollFlags::IN));
+
+    assert_eq!(2, aio.available_slots().unwrap());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn poll_cancelled_by_deadline() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, _write_end) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle) = aio_context(1, true).unwrap();
+
+    assert_matches!(
+        aio_handle
+            .submit_request_with_deadline(
+                &read_end,
+                RawCommand::Poll {
+                    events: PollFlags::IN
+                },
+                Instant::now() + Duration::from_millis(50),
+            )
+            .await,
+        Err(AioCommandError::TimedOut { cancelled: true })
+    );
+
+    assert_eq!(1, aio.available_slots().unwrap());
+
+    // dropping the pending poll cancels it in the kernel and frees the slot
+    let poll = aio_handle.poll_fd(&read_end, PollFlags::IN);
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), poll)
+            .await
+            .is_err()
+    );
+
+    while aio.available_slots().unwrap() != 1 {
+        sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_stream_read() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (_aio, aio_handle) = aio_context(4, true).unwrap();
+
+    let file = File::open(&path, false).await.unwrap();
+    let mut stream = FileStream::new(file, aio_handle, BUF_CAPACITY)
+        .await
+        .unwrap();
+
+    let mut data = vec![];
+    stream.read_to_end(&mut data).await.unwrap();
+    assert_eq!(FILE_SIZE, data.len());
+    assert!(validate_block(&data));
+
+    // unaligned seek and read across the window boundary
+    let pos = stream
+        .seek(SeekFrom::Start(BUF_CAPACITY as u64 - 100))
+        .await
+        .unwrap();
+    assert_eq!(BUF_CAPACITY as u64 - 100, pos);
+
+    let mut chunk = [0u8; 300];
+    stream.read_exact(&mut chunk).await.unwrap();
+    assert!(
+        chunk
+            .iter()
+            .enumerate()
+            .all(|(index, byte)| *byte == (pos as usize + index) as u8)
+    );
+
+    stream.seek(SeekFrom::End(-10)).await.unwrap();
+    let mut tail = vec![];
+    assert_eq!(10, stream.read_to_end(&mut tail).await.unwrap());
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_stream_write() {
+    const DATA_SIZE: usize = 3 * BUF_CAPACITY + 1234;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tmp");
+
+    let (_aio, aio_handle) = aio_context(4, true).unwrap();
+
+    let mut open_options = OpenOptions::new();
+    open_options.read(true).write(true).create_new(true);
+    let file = open_options.aio_open(path.clone(), false).await.unwrap();
+
+    let mut stream = FileStream::new(file, aio_handle, BUF_CAPACITY)
+        .await
+        .unwrap();
+
+    let data: Vec<u8> = (0..DATA_SIZE).map(|index| index as u8).collect();
+    for chunk in data.chunks(333) {
+        stream.write_all(chunk).await.unwrap();
+    }
+
+    // overwrite a few bytes in the middle of the already flushed part
+    stream.seek(SeekFrom::Start(100)).await.unwrap();
+    stream.write_all(&[0xff; 10]).await.unwrap();
+    stream.shutdown().await.unwrap();
+
+    let mut expected = data.clone();
+    expected[100..110].fill(0xff);
+
+    let written = std::fs::read(&path).unwrap();
+    assert_eq!(DATA_SIZE, written.len());
+    assert_eq!(expected, written);
+
+    stream.seek(SeekFrom::Start(0)).await.unwrap();
+    let mut read_back = vec![];
+    stream.read_to_end(&mut read_back).await.unwrap();
+    assert_eq!(expected, read_back);
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn read_into_slices() {
+    const NUM_CHUNKS: usize = 8;
+
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = Arc::new(File::open(&path, false).await.unwrap());
+
+    let (aio, aio_handle) = aio_context(NUM_CHUNKS, true).unwrap();
+
+    let arena = LockedBuf::with_size(NUM_CHUNKS * BUF_CAPACITY).unwrap();
+    let chunks = arena.into_slice().chunks(BUF_CAPACITY).unwrap();
+    assert_eq!(NUM_CHUNKS, chunks.len());
+
+    let handles: Vec<_> = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, mut chunk)| {
+            let file = file.clone();
+            let aio_handle = aio_handle.clone();
+
+            tokio::spawn(async move {
+                assert_eq!(index * BUF_CAPACITY, chunk.offset());
+
+                let read_bytes = file
+                    .read_at(
+                        &aio_handle,
+                        ((NUM_CHUNKS - index) * BUF_CAPACITY) as u64,
+                        &mut chunk,
+                        BUF_CAPACITY as _,
+                        ReadFlags::empty(),
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(BUF_CAPACITY as u64, read_bytes);
+
+                chunk
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        let chunk = handle.await.unwrap();
+        assert_eq!(BUF_CAPACITY, chunk.len());
+        assert!(validate_block(chunk.as_ref()));
+    }
+
+    assert_eq!(NUM_CHUNKS, aio.available_slots().unwrap());
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn slice_alignment() {
+    let new_slice = || LockedBuf::with_size(BUF_CAPACITY).unwrap().into_slice();
+
+    assert_matches!(
+        new_slice().split_at(100),
+        Err(LockedBufError::Misaligned {
+            offset: 100,
+            alignment: LOCKED_BUF_SLICE_ALIGNMENT
+        })
+    );
+    assert_matches!(
+        new_slice().split_at(BUF_CAPACITY * 2),
+        Err(LockedBufError::OutOfBounds { .. })
+    );
+
+    let (mut head, tail) = new_slice().split_at(LOCKED_BUF_SLICE_ALIGNMENT).unwrap();
+    assert_eq!(LOCKED_BUF_SLICE_ALIGNMENT, head.len());
+    assert_eq!(LOCKED_BUF_SLICE_ALIGNMENT, tail.offset());
+    assert_eq!(BUF_CAPACITY - LOCKED_BUF_SLICE_ALIGNMENT, tail.len());
+
+    head.as_mut().fill(1);
+    assert!(tail.as_ref().iter().all(|byte| *byte == 0));
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn locked_buf_pool() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    let (_aio, aio_handle) = aio_context(2, true).unwrap();
+
+    let pool = LockedBufPool::new(BUF_CAPACITY, 2).unwrap();
+    assert_eq!(BUF_CAPACITY, pool.buf_size());
+    assert_eq!(LockedBufPoolStats { in_use: 0, free: 2 }, pool.stats());
+
+    let mut first = pool.try_get().unwrap();
+    let second = pool.get().await;
+    assert!(pool.try_get().is_none());
+    assert_eq!(LockedBufPoolStats { in_use: 2, free: 0 }, pool.stats());
+
+    file.read_at(
+        &aio_handle,
+        0,
+        &mut first,
+        BUF_CAPACITY as _,
+        ReadFlags::empty(),
+    )
+    .await
+    .unwrap();
+    assert!(validate_block(first.as_ref()));
+
+    mem::drop(second);
+    assert_eq!(LockedBufPoolStats { in_use: 1, free: 1 }, pool.stats());
+
+    // the buffer of the dropped in-flight request returns to the pool
+    // only after the kernel completes the request
+    {
+        let mut read = Box::pin(file.read_at(
+            &aio_handle,
+            0,
+            &mut first,
+            BUF_CAPACITY as _,
+            ReadFlags::empty(),
+        ));
+
+        let (_, mut immediate) = oneshot::channel::<()>();
+
+        tokio::select! {
+            _ = &mut read => {
+                panic!("request completed unexpectedly early");
+            },
+            _ = &mut immediate => {},
+        }
+    }
+    mem::drop(first);
+
+    while pool.stats().free != 2 {
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reject_misaligned() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+
+    let alignment = file.dio_alignment();
+    assert!(alignment.memory.is_power_of_two());
+    assert!(alignment.offset.is_power_of_two());
+
+    let (aio, aio_handle) = aio_context(2, true).unwrap();
+
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    if alignment.offset > 1 {
+        assert_matches!(
+            file.read_at(
+                &aio_handle,
+                alignment.offset + 1,
+                &mut buffer,
+                BUF_CAPACITY as _,
+                ReadFlags::empty(),
+            )
+            .await,
+            Err(AioCommandError::Misaligned { what: "offset", .. })
+        );
+
+        assert_matches!(
+            file.read_at(
+                &aio_handle,
+                0,
+                &mut buffer,
+                alignment.offset - 1,
+                ReadFlags::empty(),
+            )
+            .await,
+            Err(AioCommandError::Misaligned { what: "length", .. })
+        );
+    }
+
+    assert_eq!(2, aio.available_slots().unwrap());
+
+    dir.close().unwrap();
+}
+
+#[tokio::test]
+async fn whole_file_read_write() {
+    const DATA_SIZE: usize = 1024 * 1024 + 1234;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tmp");
+
+    let (aio, aio_handle) = aio_context(2, true).unwrap();
+
+    let data: Vec<u8> = (0..DATA_SIZE).map(|index| (index % 251) as u8).collect();
+    linux_aio_tokio::fs::write(&path, &aio_handle, &data)
+        .await
+        .unwrap();
+
+    let written = std::fs::read(&path).unwrap();
+    assert_eq!(DATA_SIZE, written.len());
+    assert_eq!(data, written);
+
+    let read = linux_aio_tokio::fs::read(&path, &aio_handle).await.unwrap();
+    assert_eq!(data, read);
+
+    let file = File::open(&path, false).await.unwrap();
+    assert_eq!(data, file.read_to_end(&aio_handle).await.unwrap());
+
+    linux_aio_tokio::fs::write(&path, &aio_handle, [])
+        .await
+        .unwrap();
+    assert!(
+        linux_aio_tokio::fs::read(&path, &aio_handle)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    assert_eq!(2, aio.available_slots().unwrap());
+
+    dir.close().unwrap();
+}
+
+#[tokio::test]
+async fn context_stats() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, _write_end) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle) = aio_context(4, true).unwrap();
+
+    for _ in 0..3 {
+        file.read_at(
+            &aio_handle,
+            0,
+            &mut buffer,
+            BUF_CAPACITY as _,
+            ReadFlags::empty(),
+        )
+        .await
+        .unwrap();
+    }
+
+    let futures = aio_handle
+        .submit_batch(iter::once((-1, RawCommand::Fsync)))
+        .await
+        .unwrap();
+    for future in futures {
+        assert_matches!(future.await, Err(AioCommandError::IoSubmit(_)));
+    }
+
+    assert_matches!(
+        aio_handle
+            .submit_request_with_deadline(
+                &read_end,
+                RawCommand::Poll {
+                    events: PollFlags::IN
+                },
+                Instant::now() + Duration::from_millis(10),
+            )
+            .await,
+        Err(AioCommandError::TimedOut { cancelled: true })
+    );
+
+    let stats = aio.stats();
+    assert_eq!(stats, aio_handle.stats().unwrap());
+
+    assert_eq!(4, stats.num_slots);
+    assert_eq!(0, stats.in_flight);
+    assert_eq!(0, stats.outstanding);
+    assert_eq!(Some(4), stats.available_slots);
+
+    let pread = stats.opcode(AioOpcode::Pread);
+    assert_eq!(3, pread.submitted);
+    assert_eq!(3, pread.completed);
+    assert_eq!(0, pread.failed);
+    assert_eq!(3, pread.latency.count());
+    assert!(pread.latency.quantile(0.5).unwrap() > Duration::ZERO);
+
+    let fsync = stats.opcode(AioOpcode::Fsync);
+    assert_eq!(1, fsync.submitted);
+    assert_eq!(1, fsync.failed);
+    assert_eq!(0, fsync.latency.count());
+
+    let poll = stats.opcode(AioOpcode::Poll);
+    assert_eq!(1, poll.submitted);
+    assert_eq!(0, poll.completed);
+    assert_eq!(1, poll.cancelled);
+
+    assert_eq!(0, stats.opcode(AioOpcode::Pwrite).submitted);
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn aio_close_with_timeout() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = File::open(&path, false).await.unwrap();
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, _write_end) = unsafe {
+        (
+            Arc::new(std::fs::File::from_raw_fd(fds[0])),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle) = aio_context(4, true).unwrap();
+
+    let reads = aio_handle
+        .submit_batch(iter::once((
+            &file,
+            RawCommand::Pread {
+                offset: 0,
+                buffer: &mut buffer,
+                flags: ReadFlags::empty(),
+                len: BUF_CAPACITY as u64,
+            },
+        )))
+        .await
+        .unwrap();
+
+    // never becomes ready, so it's cancelled on close
+    let poll = task::spawn({
+        let aio_handle = aio_handle.clone();
+        let read_end = read_end.clone();
+        async move { aio_handle.poll_fd(&*read_end, PollFlags::IN).await }
+    });
+    while aio.available_slots() != Some(2) {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    let report = task::spawn(aio.close_with_timeout(Duration::from_millis(50)))
+        .await
+        .unwrap();
+    assert_eq!(
+        CloseReport {
+            completed: 1,
+            cancelled: 1,
+            abandoned: 0,
+        },
+        report
+    );
+
+    for read in reads {
+        assert_eq!(BUF_CAPACITY as u64, read.await.unwrap());
+    }
+    assert_eq!(PollFlags::empty(), poll.await.unwrap().unwrap());
+
+    assert_matches!(
+        aio_handle.poll_fd(&*read_end, PollFlags::IN).await,
+        Err(AioCommandError::AioStopped)
+    );
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn close_rejects_permit_waiters() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let file = Arc::new(File::open(&path, false).await.unwrap());
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    let (aio, aio_handle) = aio_context(1, true).unwrap();
+
+    let mut reads = aio_handle
+        .submit_batch(iter::once((
+            &*file,
+            RawCommand::Pread {
+                offset: 0,
+                buffer: &mut buffer,
+                flags: ReadFlags::empty(),
+                len: BUF_CAPACITY as u64,
+            },
+        )))
+        .await
+        .unwrap();
+
+    // waits for the permit, held by the read, which is not awaited yet
+    let waiter = task::spawn({
+        let file = file.clone();
+        let aio_handle = aio_handle.clone();
+        async move {
+            let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+            file.read_at(
+                &aio_handle,
+                0,
+                &mut buffer,
+                BUF_CAPACITY as _,
+                ReadFlags::empty(),
+            )
+            .await
+        }
+    });
+    sleep(Duration::from_millis(50)).await;
+
+    aio.close().await;
+
+    assert_eq!(BUF_CAPACITY as u64, reads.pop().unwrap().await.unwrap());
+    assert_matches!(
+        tokio::time::timeout(Duration::from_secs(5), waiter)
+            .await
+            .unwrap()
+            .unwrap(),
+        Err(AioCommandError::AioStopped)
+    );
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn aio_close_with_timeout_polls() {
+    let pipe = || {
+        let mut fds = [0 as RawFd; 2];
+        assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+        unsafe {
+            (
+                Arc::new(std::fs::File::from_raw_fd(fds[0])),
+                std::fs::File::from_raw_fd(fds[1]),
+            )
+        }
+    };
+    // the first one never becomes ready, so its poll is cancelled on close
+    let (stuck_read_end, _stuck_write_end) = pipe();
+    let (ready_read_end, mut ready_write_end) = pipe();
+
+    let (aio, aio_handle) = aio_context(4, true).unwrap();
+
+    let polls: Vec<_> = [stuck_read_end.clone(), ready_read_end]
+        .into_iter()
+        .map(|read_end| {
+            let aio_handle = aio_handle.clone();
+            task::spawn(async move { aio_handle.poll_fd(&*read_end, PollFlags::IN).await })
+        })
+        .collect();
+    while aio.available_slots() != Some(2) {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    let close = task::spawn(aio.close_with_timeout(Duration::from_millis(200)));
+    sleep(Duration::from_millis(20)).await;
+    ready_write_end.write_all(b"x").unwrap();
+
+    assert_eq!(
+        CloseReport {
+            completed: 1,
+            cancelled: 1,
+            abandoned: 0,
+        },
+        close.await.unwrap()
+    );
+
+    let mut results = vec![];
+    for poll in polls {
+        results.push(poll.await.unwrap().unwrap());
+    }
+    assert_eq!(vec![PollFlags::empty(), PollFlags::IN], results);
+
+    assert_matches!(
+        aio_handle.poll_fd(&*stuck_read_end, PollFlags::IN).await,
+        Err(AioCommandError::AioStopped)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropped_context_serves_in_flight() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, mut write_end) = unsafe {
+        (
+            Arc::new(std::fs::File::from_raw_fd(fds[0])),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle) = aio_context(2, true).unwrap();
+    assert_matches!(aio.health(), PollerHealth::Running);
+
+    let poll = task::spawn({
+        let aio_handle = aio_handle.clone();
+        let read_end = read_end.clone();
+        async move { aio_handle.poll_fd(&*read_end, PollFlags::IN).await }
+    });
+    while aio.available_slots() != Some(1) {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    mem::drop(aio);
+
+    assert_matches!(
+        aio_handle.poll_fd(&*read_end, PollFlags::IN).await,
+        Err(AioCommandError::AioStopped)
+    );
+
+    write_end.write_all(b"x").unwrap();
+    assert_eq!(PollFlags::IN, poll.await.unwrap().unwrap());
+
+    while !matches!(aio_handle.health(), PollerHealth::Stopped) {
+        sleep(Duration::from_millis(1)).await;
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropped_poller_aborts_waiters() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, _write_end) = unsafe {
+        (
+            Arc::new(std::fs::File::from_raw_fd(fds[0])),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle, background): (AioContext, AioContextHandle, _) =
+        generic_aio_context(2, true).unwrap();
+    let background = task::spawn(background);
+
+    let poll = task::spawn({
+        let aio_handle = aio_handle.clone();
+        let read_end = read_end.clone();
+        async move { aio_handle.poll_fd(&*read_end, PollFlags::IN).await }
+    });
+    while aio.available_slots() != Some(1) {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    // the background future is dropped without completing
+    background.abort();
+    assert!(background.await.unwrap_err().is_cancelled());
+
+    assert_matches!(poll.await.unwrap(), Err(AioCommandError::AioStopped));
+    assert_matches!(aio.health(), PollerHealth::Stopped);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn dropped_poller_completes_close() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, _write_end) = unsafe {
+        (
+            Arc::new(std::fs::File::from_raw_fd(fds[0])),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle, background): (AioContext, AioContextHandle, _) =
+        generic_aio_context(2, true).unwrap();
+    let background = task::spawn(background);
+
+    let poll = task::spawn({
+        let aio_handle = aio_handle.clone();
+        let read_end = read_end.clone();
+        async move { aio_handle.poll_fd(&*read_end, PollFlags::IN).await }
+    });
+    while aio_handle.available_slots() != Some(1) {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    // waits for the poll, which never becomes ready
+    let close = task::spawn(aio.close());
+    sleep(Duration::from_millis(20)).await;
+    assert!(!close.is_finished());
+
+    background.abort();
+
+    assert_matches!(poll.await.unwrap(), Err(AioCommandError::AioStopped));
+    assert_matches!(aio_handle.health(), PollerHealth::Stopped);
+    tokio::time::timeout(Duration::from_secs(5), close)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn context_pool() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    for routing in [ShardRouting::ThreadAffinity, ShardRouting::FdHash] {
+        let pool = AioContextPool::new(3, 2, true, routing).unwrap();
+        let pool_handle = pool.handle();
+
+        assert_eq!(3, pool.num_contexts());
+        assert_eq!(Some(6), pool.available_slots());
+
+        let reads: Vec<_> = (0..8)
+            .map(|_| {
+                let pool_handle = pool_handle.clone();
+                let path = path.clone();
+
+                task::spawn(async move {
+                    let file = File::open(&path, false).await.unwrap();
+                    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+                    file.read_at(
+                        pool_handle.handle_for(&file),
+                        0,
+                        &mut buffer,
+                        BUF_CAPACITY as _,
+                        ReadFlags::empty(),
+                    )
+                    .await
+                    .unwrap();
+                    assert!(validate_block(buffer.as_ref()));
+
+                    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+                    pool_handle
+                        .submit_request(
+                            &file,
+                            RawCommand::Pread {
+                                offset: BUF_CAPACITY as u64,
+                                buffer: &mut buffer,
+                                flags: ReadFlags::empty(),
+                                len: BUF_CAPACITY as u64,
+                            },
+                        )
+                        .await
+                        .unwrap();
+                    assert!(validate_block(buffer.as_ref()));
+                })
+            })
+            .collect();
+
+        for read in reads {
+            read.await.unwrap();
+        }
+
+        assert_eq!(Some(6), pool_handle.available_slots());
+
+        pool.close().await;
+
+        let file = std::fs::File::open(&path).unwrap();
+        assert_matches!(
+            pool_handle.submit_request(&file, RawCommand::Fsync).await,
+            Err(AioCommandError::AioStopped)
+        );
+    }
+
+    dir.close().unwrap();
+}
+
+#[tokio::test]
+async fn empty_context_pool() {
+    assert_matches!(
+        AioContextPool::new(0, 2, true, ShardRouting::FdHash),
+        Err(AioContextError::IoSetup(e)) if e.raw_os_error() == Some(libc::EINVAL)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn busy_poll_completions() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (aio, aio_handle) = aio_context_with_options(
+        AioContextOptions::new(4).completion_mode(CompletionMode::BusyPoll { idle_polls: 16 }),
+    )
+    .unwrap();
+
+    let reads: Vec<_> = (0..32)
+        .map(|index| {
+            let aio_handle = aio_handle.clone();
+            let path = path.clone();
+
+            task::spawn(async move {
+                let file = File::open(&path, false).await.unwrap();
+                let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+                let read_bytes = file
+                    .read_at(
+                        &aio_handle,
+                        (index % 16 * BUF_CAPACITY) as u64,
+                        &mut buffer,
+                        BUF_CAPACITY as _,
+                        ReadFlags::empty(),
+                    )
+                    .await
+                    .unwrap();
+
+                assert_eq!(read_bytes, BUF_CAPACITY as u64);
+                assert!(validate_block(buffer.as_ref()));
+            })
+        })
+        .collect();
+
+    for read in reads {
+        read.await.unwrap();
+    }
+
+    assert_eq!(4, aio.available_slots().unwrap());
+    assert_eq!(32, aio.stats().opcode(AioOpcode::Pread).completed);
+
+    // idle context goes back to eventfd, and still receives completions
+    sleep(Duration::from_millis(50)).await;
+
+    let file = File::open(&path, false).await.unwrap();
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+    file.read_at(
+        &aio_handle,
+        0,
+        &mut buffer,
+        BUF_CAPACITY as _,
+        ReadFlags::empty(),
+    )
+    .await
+    .unwrap();
+    assert!(validate_block(buffer.as_ref()));
+
+    aio.close().await;
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn polled_completions() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (aio, aio_handle) = aio_context_with_options(AioContextOptions::new(4).completion_mode(
+        CompletionMode::Polled {
+            spin: Duration::from_micros(200),
+        },
+    ))
+    .unwrap();
+
+    let file = File::open(&path, true).await.unwrap();
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    for index in 0..16 {
+        let read_bytes = file
+            .read_at(
+                &aio_handle,
+                (index * BUF_CAPACITY) as u64,
+                &mut buffer,
+                BUF_CAPACITY as _,
+                ReadFlags::HIPRI,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(read_bytes, BUF_CAPACITY as u64);
+        assert!(validate_block(buffer.as_ref()));
+
+        // let the polling stop, so that the next request is picked up after a wait
+        if index % 4 == 0 {
+            sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    assert_eq!(4, aio.available_slots().unwrap());
+    assert_eq!(16, aio.stats().opcode(AioOpcode::Pread).completed);
+
+    aio.close().await;
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn reaper_block_on() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    // no async runtime here, the thread reaps completions while blocked
+    let (aio, aio_handle, reaper) = aio_context_with_reaper(AioContextOptions::new(2)).unwrap();
+
+    assert_eq!(
+        0,
+        reaper
+            .reap_blocking(Some(Duration::from_millis(10)))
+            .unwrap()
+    );
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    let read_bytes = reaper
+        .block_on(aio_handle.submit_request(
+            &file,
+            RawCommand::Pread {
+                offset: 0,
+                buffer: &mut buffer,
+                len: BUF_CAPACITY as u64,
+                flags: ReadFlags::empty(),
+            },
+        ))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(read_bytes, BUF_CAPACITY as u64);
+    assert!(validate_block(buffer.as_ref()));
+    assert_eq!(Some(2), aio.available_slots());
+
+    reaper.block_on(aio.close()).unwrap();
+
+    assert_matches!(
+        reaper.block_on(aio_handle.submit_request(&file, RawCommand::Fsync)),
+        Ok(Err(AioCommandError::AioStopped))
+    );
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn reaper_thread_pool_workers() {
+    const NUM_POLLS: usize = 64;
+
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, mut write_end) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    // without async runtime, the requests are executed by the dedicated threads
+    let (aio, aio_handle, reaper) =
+        aio_context_with_reaper(AioContextOptions::new(NUM_POLLS).backend(AioBackend::ThreadPool))
+            .unwrap();
+
+    let polls = reaper
+        .block_on(aio_handle.submit_batch((0..NUM_POLLS).map(|_| {
+            (
+                read_end.as_raw_fd(),
+                RawCommand::Poll {
+                    events: PollFlags::IN,
+                },
+            )
+        })))
+        .unwrap()
+        .unwrap();
+
+    // the waiting polls hold the threads, the rest are queued
+    write_end.write_all(b"x").unwrap();
+
+    for poll in polls {
+        assert_eq!(
+            PollFlags::IN.bits() as u64,
+            reaper.block_on(poll).unwrap().unwrap()
+        );
+    }
+
+    // the workers are named before they take the first job, and stay idle until close
+    let num_workers = std::fs::read_dir("/proc/self/task")
+        .unwrap()
+        .filter(|task| {
+            std::fs::read_to_string(task.as_ref().unwrap().path().join("comm"))
+                .is_ok_and(|comm| comm.trim() == "aio-blocking")
+        })
+        .count();
+    let num_cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    assert!(num_workers > 0);
+    assert!(num_workers <= NUM_POLLS.min(4 * num_cores));
+
+    reaper.block_on(aio.close()).unwrap();
+}
+
+#[tokio::test]
+async fn reaper_eventfd() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (aio, aio_handle, reaper) = aio_context_with_reaper(AioContextOptions::new(4)).unwrap();
+
+    // the eventfd is watched by the event loop, which knows nothing about the context
+    let reaping = task::spawn(async move {
+        let eventfd =
+            AsyncFd::with_interest(reaper.eventfd().as_raw_fd(), Interest::READABLE).unwrap();
+        let mut num_reaped = 0;
+
+        while num_reaped < 8 {
+            eventfd.readable().await.unwrap().clear_ready();
+            num_reaped += reaper.reap().unwrap();
+        }
+
+        num_reaped
+    });
+
+    let reads: Vec<_> = (0..8)
+        .map(|index| {
+            let aio_handle = aio_handle.clone();
+            let path = path.clone();
+
+            task::spawn(async move {
+                let file = std::fs::File::open(&path).unwrap();
+                let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+                aio_handle
+                    .submit_request(
+                        &file,
+                        RawCommand::Pread {
+                            offset: (index * BUF_CAPACITY) as u64,
+                            buffer: &mut buffer,
+                            len: BUF_CAPACITY as u64,
+                            flags: ReadFlags::empty(),
+                        },
+                    )
+                    .await
+                    .unwrap();
+
+                assert!(validate_block(buffer.as_ref()));
+            })
+        })
+        .collect();
+
+    for read in reads {
+        read.await.unwrap();
+    }
+
+    assert_eq!(8, reaping.await.unwrap());
+    assert_eq!(8, aio.stats().opcode(AioOpcode::Pread).completed);
+
+    aio.close().await;
+
+    dir.close().unwrap();
+}
+
+#[tokio::test]
+async fn read_exact_write_all() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (aio, aio_handle) = aio_context(2, true).unwrap();
+
+    let file = File::open(&path, false).await.unwrap();
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    file.read_exact_at(
+        &aio_handle,
+        BUF_CAPACITY as u64,
+        &mut buffer,
+        BUF_CAPACITY as _,
+        ReadFlags::empty(),
+    )
+    .await
+    .unwrap();
+    assert!(validate_block(buffer.as_ref()));
+
+    // the second half of the buffer is past the end of file
+    let half = BUF_CAPACITY as u64 / 2;
+    assert_matches!(
+        file.read_exact_at(
+            &aio_handle,
+            FILE_SIZE as u64 - half,
+            &mut buffer,
+            BUF_CAPACITY as _,
+            ReadFlags::empty(),
+        )
+        .await,
+        Err(AioCommandError::UnexpectedEof { len, transferred })
+            if len == BUF_CAPACITY as u64 && transferred == half
+    );
+
+    let mut open_options = OpenOptions::new();
+    open_options.write(true);
+
+    let file = open_options.aio_open(path.clone(), false).await.unwrap();
+    fill_pattern(7, buffer.as_mut());
+
+    file.write_all_at(
+        &aio_handle,
+        FILE_SIZE as u64,
+        &buffer,
+        BUF_CAPACITY as _,
+        WriteFlags::empty(),
+    )
+    .await
+    .unwrap();
+
+    let file = File::open(&path, false).await.unwrap();
+    let mut read_back = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+    file.read_exact_at(
+        &aio_handle,
+        FILE_SIZE as u64,
+        &mut read_back,
+        BUF_CAPACITY as _,
+        ReadFlags::empty(),
+    )
+    .await
+    .unwrap();
+    assert!(validate_pattern(7, read_back.as_ref()));
+
+    aio.close().await;
+
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn io_uring_backend() {
+    // falls back to kernel AIO, if io_uring is not available
+    check_backend(AioBackend::IoUring).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn thread_pool_backend() {
+    check_backend(AioBackend::ThreadPool).await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn io_uring_cancel_refused() {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (aio, aio_handle) =
+        aio_context_with_options(AioContextOptions::new(4).backend(AioBackend::IoUring)).unwrap();
+    if aio.backend() != AioBackend::IoUring {
+        return;
+    }
+
+    let mut open_options = OpenOptions::new();
+    open_options.read(true).write(true);
+    let file = open_options.aio_open(path.clone(), true).await.unwrap();
+
+    let mut futures = aio_handle
+        .submit_batch(iter::once((&file, RawCommand::Fsync)))
+        .await
+        .unwrap();
+    let future = futures.pop().unwrap();
+
+    // the completion is delivered, but not taken by the future yet
+    sleep(Duration::from_millis(100)).await;
+
+    assert_matches!(future.cancel(), Err(AioCommandError::IoCancel(_)));
+    assert_eq!(0, future.await.unwrap());
+
+    aio.close().await;
+    dir.close().unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn io_uring_dropped_poll_reclaimed() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, _write_end) = unsafe {
+        (
+            Arc::new(std::fs::File::from_raw_fd(fds[0])),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle) =
+        aio_context_with_options(AioContextOptions::new(2).backend(AioBackend::IoUring)).unwrap();
+    if aio.backend() != AioBackend::IoUring {
+        return;
+    }
+
+    let poll = task::spawn({
+        let aio_handle = aio_handle.clone();
+        let read_end = read_end.clone();
+        async move { aio_handle.poll_fd(&*read_end, PollFlags::IN).await }
+    });
+    while aio.available_slots() != Some(1) {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    // the cancellation is only requested on drop, and the poller reclaims the slot
+    poll.abort();
+    assert!(poll.await.unwrap_err().is_cancelled());
+
+    while aio.available_slots() != Some(2) {
+        sleep(Duration::from_millis(1)).await;
+    }
+    let stats = aio.stats();
+    assert_eq!(0, stats.outstanding);
+    assert_eq!(1, stats.opcode(AioOpcode::Poll).cancelled);
+
+    aio.close().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn thread_pool_cancel_refused() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, mut write_end) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (aio, aio_handle) =
+        aio_context_with_options(AioContextOptions::new(2).backend(AioBackend::ThreadPool))
+            .unwrap();
+
+    let mut futures = aio_handle
+        .submit_batch(iter::once((
+            read_end.as_raw_fd(),
+            RawCommand::Poll {
+                events: PollFlags::IN,
+            },
+        )))
+        .await
+        .unwrap();
+    let future = futures.pop().unwrap();
+
+    // the completion is delivered, but not taken by the future yet
+    write_end.write_all(b"x").unwrap();
+    while aio.stats().opcode(AioOpcode::Poll).completed == 0 {
+        sleep(Duration::from_millis(1)).await;
+    }
+
+    assert_matches!(future.cancel(), Err(AioCommandError::IoCancel(_)));
+    assert_eq!(PollFlags::IN.bits() as u64, future.await.unwrap());
+    assert_eq!(0, aio.stats().opcode(AioOpcode::Poll).cancelled);
+
+    aio.close().await;
+}
+
+#[test]
+fn thread_pool_drop_doesnt_wait() {
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, mut write_end) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    let (dropped_tx, dropped_rx) = std::sync::mpsc::channel();
+    let dropping = std::thread::spawn(move || {
+        let (aio, aio_handle, reaper) =
+            aio_context_with_reaper(AioContextOptions::new(2).backend(AioBackend::ThreadPool))
+                .unwrap();
+
+        let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+        let reads = reaper
+            .block_on(aio_handle.submit_batch(iter::once((
+                read_end.as_raw_fd(),
+                RawCommand::Pread {
+                    // -1 is the current position for preadv2, which allows reading pipes
+                    offset: u64::MAX,
+                    buffer: &mut buffer,
+                    flags: ReadFlags::empty(),
+                    len: BUF_CAPACITY as u64,
+                },
+            ))))
+            .unwrap()
+            .unwrap();
+
+        // the read from the empty pipe can't be cancelled, so the context is released
+        // while it's still in flight
+        mem::drop(reads);
+        mem::drop(aio);
+        mem::drop(reaper);
+        mem::drop(buffer);
+        assert!(aio_handle.backend().is_none());
+        dropped_tx.send(()).unwrap();
+
+        read_end
+    });
+
+    let dropped = dropped_rx.recv_timeout(Duration::from_secs(5));
+
+    // the read completes into the buffer, kept by the pool
+    write_end.write_all(b"x").unwrap();
+    mem::drop(dropping.join().unwrap());
+
+    assert!(dropped.is_ok());
+}
+
+async fn check_backend(backend: AioBackend) {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (aio, aio_handle) =
+        aio_context_with_options(AioContextOptions::new(4).backend(backend)).unwrap();
+    assert_eq!(Some(aio.backend()), aio_handle.backend());
+    assert!(format!("{:?}", aio).contains(&format!("{:?}", aio.backend())));
+    // the fallback is reported along with the backend
+    assert_eq!(aio.backend() != backend, aio.fallback().is_some());
+
+    let mut open_options = OpenOptions::new();
+    open_options.read(true).write(true);
+    let file = open_options.aio_open(path.clone(), true).await.unwrap();
+
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+    fill_pattern(65u8, buffer.as_mut());
+    let wrote_bytes = file
+        .write_at(
+            &aio_handle,
+            16384,
+            &buffer,
+            BUF_CAPACITY as _,
+            WriteFlags::DSYNC,
+        )
+        .await
+        .unwrap();
+    assert_eq!(BUF_CAPACITY as u64, wrote_bytes);
+
+    file.sync_all(&aio_handle).await.unwrap();
+
+    let read_bytes = file
+        .read_at(
+            &aio_handle,
+            16384,
+            &mut buffer,
+            BUF_CAPACITY as _,
+            ReadFlags::empty(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(BUF_CAPACITY as u64, read_bytes);
+    assert!(validate_pattern(65u8, buffer.as_ref()));
+
+    let mut fds = [0 as RawFd; 2];
+    assert_eq!(0, unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) });
+    let (read_end, mut write_end) = unsafe {
+        (
+            std::fs::File::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    };
+
+    assert_matches!(
+        aio_handle
+            .submit_request_with_deadline(
+                &read_end,
+                RawCommand::Poll {
+                    events: PollFlags::IN
+                },
+                Instant::now() + Duration::from_millis(50),
+            )
+            .await,
+        Err(AioCommandError::TimedOut { cancelled: true })
+    );
+
+    write_end.write_all(b"ping").unwrap();
+    let revents = aio_handle.poll_fd(&read_end, PollFlags::IN).await.unwrap();
+    assert!(revents.contains(PollFlags::IN));
+
+    assert_eq!(4, aio.available_slots().unwrap());
+    assert_eq!(1, aio.stats().opcode(AioOpcode::Pread).completed);
+
+    aio.close().await;
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn io_uring_reaper_block_on() {
+    check_reaper_backend(AioBackend::IoUring);
+}
+
+#[test]
+fn thread_pool_reaper_block_on() {
+    // without tokio runtime, the requests are run on dedicated threads
+    check_reaper_backend(AioBackend::ThreadPool);
+}
+
+fn check_reaper_backend(backend: AioBackend) {
+    let (dir, path) = create_filled_tempfile(FILE_SIZE);
+
+    let (aio, aio_handle, reaper) =
+        aio_context_with_reaper(AioContextOptions::new(2).backend(backend)).unwrap();
+
+    assert_eq!(
+        0,
+        reaper
+            .reap_blocking(Some(Duration::from_millis(10)))
+            .unwrap()
+    );
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mut buffer = LockedBuf::with_size(BUF_CAPACITY).unwrap();
+
+    let read_bytes = reaper
+        .block_on(aio_handle.submit_request(
+            &file,
+            RawCommand::Pread {
+                offset: 0,
+                buffer: &mut buffer,
+                len: BUF_CAPACITY as u64,
+                flags: ReadFlags::empty(),
+            },
+        ))
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(read_bytes, BUF_CAPACITY as u64);
+    assert!(validate_block(buffer.as_ref()));
+
+    reaper.block_on(aio.close()).unwrap();
+
+    dir.close().unwrap();
+}
+
+#[tokio::test]
+async fn system_limit() {
+    let max_nr: usize = std::fs::read_to_string("/proc/sys/fs/aio-max-nr")
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+
+    // rejected before io_setup, so no slots are taken from other contexts
+    assert_matches!(
+        aio_context_with_options(AioContextOptions::new(max_nr + 1).fail_on_system_limit(true)),
+        Err(AioContextError::SystemLimit { requested, available }) if requested == max_nr + 1 && available <= max_nr
+    );
+    assert_matches!(
+        aio_context_with_options(
+            AioContextOptions::new(max_nr + 1)
+                .shrink_to(max_nr + 1)
+                .fail_on_system_limit(true)
+        ),
+        Err(AioContextError::SystemLimit { .. })
+    );
+
+    // otherwise degrades to the blocking thread pool with all requested slots
+    let (aio, _aio_handle) =
+        aio_context_with_options(AioContextOptions::new(max_nr + 1).shrink_to(max_nr + 1)).unwrap();
+    assert_eq!(AioBackend::ThreadPool, aio.backend());
+    assert_eq!(max_nr + 1, aio.num_slots());
+    mem::drop(aio);
+
+    // and reports why
+    let (aio, _aio_handle) = aio_context(max_nr + 1, true).unwrap();
+    assert_eq!(AioBackend::ThreadPool, aio.backend());
+    assert_matches!(
+        aio.fallback(),
+        Some(&AioFallback::SystemLimit { requested, available }) if requested == max_nr + 1 && available <= max_nr
+    );
+    assert!(format!("{:?}", aio).contains("SystemLimit"));
+    mem::drop(aio);
+
+    // the limit allows the small context as is
+    let (aio, _aio_handle) =
+        aio_context_with_options(AioContextOptions::new(4).shrink_to(1)).unwrap();
+    assert_matches!(aio.fallback(), None);
+    assert_eq!(4, aio.num_slots());
+    assert_eq!(Some(4), aio.available_slots());
+}